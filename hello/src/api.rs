@@ -0,0 +1,347 @@
+//! JSON APIs
+//!
+//! API handlers are plain functions that take typed extractors and return
+//! anything that implements `IntoResponse`, for example:
+//!
+//! ```
+//! use hello::api::{self, ApiError, Json, Path};
+//! use hello::router::Router;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct User {
+//!     id: u32,
+//! }
+//!
+//! fn show_user(Path(user): Path<User>) -> Result<Json<u32>, ApiError> {
+//!     Ok(Json(user.id))
+//! }
+//!
+//! let router = Router::new().get("/api/users/:id", api::handler(show_user));
+//! ```
+//!
+//! Extractors are `Path`, `Query` and `Json`, and tuples of them.
+//! When an extractor fails, the handler isn't called, and the client gets
+//! an `application/problem+json` response (RFC 9457) that explains why.
+
+use std::io;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+use crate::router::Handler;
+
+/// The largest JSON body that `Json` accepts, in bytes
+pub const MAX_JSON_BODY: u64 = 1024 * 1024;
+
+/// The media type of JSON bodies
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// The media type of problem details
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Something that can be turned into a response
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+    /// Respond with `T`, but with a different status
+    fn into_response(self) -> Response {
+        let mut response = self.1.into_response();
+        response.status = self.0;
+        response
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+/// Something that can be extracted from a request
+pub trait FromRequest: Sized {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError>;
+}
+
+impl FromRequest for () {
+    /// Extract nothing, for handlers that don't need the request
+    fn from_request(_request: &mut Request) -> Result<Self, ApiError> {
+        Ok(())
+    }
+}
+
+impl<A: FromRequest, B: FromRequest> FromRequest for (A, B) {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        Ok((A::from_request(request)?, B::from_request(request)?))
+    }
+}
+
+impl<A: FromRequest, B: FromRequest, C: FromRequest> FromRequest for (A, B, C) {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        Ok((A::from_request(request)?, B::from_request(request)?, C::from_request(request)?))
+    }
+}
+
+/// Turn a function that takes extractors into a `Handler`
+///
+/// Handlers with several extractors take them as a tuple.
+/// If extraction fails, the error is returned instead of calling `f`.
+pub fn handler<T, R, F>(f: F) -> impl Handler
+where
+    T: FromRequest,
+    R: IntoResponse,
+    F: Fn(T) -> R + Send + Sync,
+{
+    move |request: &mut Request| match T::from_request(request) {
+        Ok(input) => f(input).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// The parameters captured from the path by the router
+///
+/// Values are parsed like a query string, so `T` is usually a struct
+/// with a field for each parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        serde_urlencoded::to_string(&request.params)
+            .ok()
+            .and_then(|params| serde_urlencoded::from_str(&params).ok())
+            .map(Path)
+            .ok_or_else(|| ApiError::new(StatusCode::NotFound, "The path parameters aren't valid."))
+    }
+}
+
+/// The query string, parsed into `T`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        serde_urlencoded::from_str(request.query().unwrap_or_default())
+            .map(Query)
+            .map_err(|error| ApiError::new(StatusCode::BadRequest, format!("Invalid query string: {error}.")))
+    }
+}
+
+/// A JSON body
+///
+/// As an extractor, it requires a JSON `Content-Type` and a body
+/// of at most `MAX_JSON_BODY` bytes. Malformed JSON, or a body that can't be read,
+/// like a truncated chunked one, is a `400 Bad Request`,
+/// and well-formed JSON of the wrong shape is a `422 Unprocessable Entity`.
+///
+/// As a response, it serializes `T` with the `application/json` content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        if !request.header("Content-Type").is_some_and(is_json) {
+            return Err(ApiError::new(
+                StatusCode::UnsupportedMediaType,
+                format!("Expected a body of type '{JSON_CONTENT_TYPE}'."),
+            ));
+        }
+
+        if request.content_length().is_some_and(|len| len > MAX_JSON_BODY) {
+            return Err(too_large());
+        }
+
+        let body = request.read_body(MAX_JSON_BODY).map_err(|error| match error.kind() {
+            io::ErrorKind::FileTooLarge => too_large(),
+            _ => ApiError::new(StatusCode::BadRequest, format!("Couldn't read the body: {error}")),
+        })?;
+
+        serde_json::from_slice(&body).map(Json).map_err(|error| {
+            let status = if error.is_data() { StatusCode::UnprocessableEntity } else { StatusCode::BadRequest };
+            ApiError::new(status, format!("Invalid JSON body: {error}."))
+        })
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => Response::builder()
+                .header("Content-Type", JSON_CONTENT_TYPE)
+                .bytes(body)
+                .build(),
+            Err(error) => ApiError::new(StatusCode::InternalServerError, error.to_string()).into_response(),
+        }
+    }
+}
+
+/// Check whether a `Content-Type` is JSON, including types like `application/problem+json`
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    media_type == JSON_CONTENT_TYPE || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+fn too_large() -> ApiError {
+    ApiError::new(StatusCode::PayloadTooLarge, format!("The body is larger than {MAX_JSON_BODY} bytes."))
+}
+
+/// An error that is sent to the client as problem details (RFC 9457)
+///
+/// The title is the status's reason phrase; the detail explains this occurrence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub detail: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> ApiError {
+        ApiError { status, detail: detail.into() }
+    }
+}
+
+/// The problem details object, as it is serialized
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let problem = Problem {
+            kind: "about:blank",
+            title: self.status.reason(),
+            status: self.status.code(),
+            detail: &self.detail,
+        };
+
+        Response::builder()
+            .status(self.status)
+            .header("Content-Type", PROBLEM_CONTENT_TYPE)
+            .bytes(serde_json::to_vec(&problem).expect("Expected to serialize problem details."))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::response::Body;
+    use crate::router::Router;
+
+    #[derive(Debug, Deserialize)]
+    struct Item {
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Page {
+        page: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct NewItem {
+        name: String,
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get(
+                "/items/:id",
+                handler(|(Path(item), Query(page)): (Path<Item>, Query<Page>)| {
+                    Json(json!({ "id": item.id, "page": page.page.unwrap_or(1) }))
+                }),
+            )
+            .post(
+                "/items",
+                handler(|Json(item): Json<NewItem>| -> Result<_, ApiError> {
+                    if item.name.is_empty() {
+                        return Err(ApiError::new(StatusCode::UnprocessableEntity, "The name is empty."));
+                    }
+                    Ok((StatusCode::Created, Json(item)))
+                }),
+            )
+    }
+
+    fn send(raw: &str) -> (Response, Value) {
+        let response = router().handle(&mut Request::parse(raw.as_bytes()).unwrap());
+        let body = match &response.body {
+            Body::Bytes(body) => serde_json::from_slice(body).unwrap(),
+            _ => Value::Null,
+        };
+        (response, body)
+    }
+
+    fn post(content_type: &str, body: &str) -> (Response, Value) {
+        send(&format!(
+            "POST /items HTTP/1.1\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ))
+    }
+
+    #[test]
+    fn test_path_and_query() {
+        let (response, body) = send("GET /items/7?page=3 HTTP/1.1\r\n\r\n");
+        assert_eq!(StatusCode::Ok, response.status);
+        assert_eq!(Some(JSON_CONTENT_TYPE), response.headers.get("Content-Type"));
+        assert_eq!(json!({ "id": 7, "page": 3 }), body);
+    }
+
+    #[test]
+    fn test_invalid_path_and_query() {
+        let (response, body) = send("GET /items/seven HTTP/1.1\r\n\r\n");
+        assert_eq!(StatusCode::NotFound, response.status);
+        assert_eq!(Some(PROBLEM_CONTENT_TYPE), response.headers.get("Content-Type"));
+        assert_eq!("about:blank", body["type"]);
+        assert_eq!("Not Found", body["title"]);
+        assert_eq!(404, body["status"]);
+
+        let (response, _) = send("GET /items/7?page=last HTTP/1.1\r\n\r\n");
+        assert_eq!(StatusCode::BadRequest, response.status);
+    }
+
+    #[test]
+    fn test_json_body() {
+        let (response, body) = post("application/json; charset=utf-8", r#"{"name":"lamp"}"#);
+        assert_eq!(StatusCode::Created, response.status);
+        assert_eq!(json!({ "name": "lamp" }), body);
+
+        let (response, body) = post(JSON_CONTENT_TYPE, r#"{"name":""}"#);
+        assert_eq!(StatusCode::UnprocessableEntity, response.status);
+        assert_eq!("The name is empty.", body["detail"]);
+    }
+
+    #[test]
+    fn test_json_body_errors() {
+        assert_eq!(StatusCode::UnsupportedMediaType, post("text/plain", r#"{"name":"lamp"}"#).0.status);
+        assert_eq!(StatusCode::BadRequest, post(JSON_CONTENT_TYPE, r#"{"name":"#).0.status);
+        assert_eq!(StatusCode::UnprocessableEntity, post(JSON_CONTENT_TYPE, r#"{"title":"lamp"}"#).0.status);
+
+        let too_large = format!(r#"{{"name":"{}"}}"#, "x".repeat(MAX_JSON_BODY as usize));
+        assert_eq!(StatusCode::PayloadTooLarge, post(JSON_CONTENT_TYPE, &too_large).0.status);
+
+        let truncated = send(&format!(
+            "POST /items HTTP/1.1\r\nContent-Type: {JSON_CONTENT_TYPE}\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n{{\"name\""
+        ));
+        assert_eq!(StatusCode::BadRequest, truncated.0.status);
+    }
+}
@@ -0,0 +1,135 @@
+//! The pages and middleware of our web server
+//!
+//! Kept in the library, rather than in `main.rs`,
+//! so that the integration tests can serve the same router.
+//!
+//! The sites from the server configuration are served as virtual hosts.
+//! Sites without a document root, and unknown hosts unless the configuration
+//! says otherwise, get the built-in pages. The middleware runs for all sites.
+
+use std::{
+    env,
+    net::TcpStream,
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api::{self, Json},
+    config::{ServerConfig, SiteConfig},
+    constants::*,
+    middleware::{AccessLog, RequestId},
+    proxy::{Proxy, HEALTH_CHECK_INTERVAL},
+    rate_limit::{Limit, RateLimiter},
+    request::Request,
+    response::{Response, StatusCode},
+    router::{Handler, Router},
+    static_files::StaticFiles,
+    vhost::VirtualHosts,
+    websocket::{self, WebSocket, CLOSE_NORMAL},
+    PoolStats,
+};
+
+/// Build the router with all the sites and middleware of the server
+///
+/// `stats` are the statistics of the pool that serves the requests.
+///
+/// The access log is registered first, so that it also logs requests that
+/// other middleware short-circuits, and sees the IDs that `RequestId` assigns.
+pub fn router(config: &ServerConfig, stats: Arc<PoolStats>) -> Router {
+    let pages: Arc<dyn Handler> = Arc::new(pages(stats));
+
+    let default = match config.sites.iter().find(|site| site.default) {
+        Some(site) => site_handler(site, &pages),
+        None => Arc::clone(&pages),
+    };
+
+    let mut hosts = VirtualHosts::new(default);
+    for site in &config.sites {
+        let names: Vec<&str> = site.hosts.iter().map(String::as_str).collect();
+        hosts = hosts.site(&names, site_handler(site, &pages));
+    }
+
+    let rate_limiter = RateLimiter::new(Limit::per_second(RATE_LIMIT_PER_SEC).burst(RATE_LIMIT_BURST))
+        .route(SLEEP_PATH, Limit::per_minute(SLEEP_RATE_LIMIT_PER_MIN).burst(SLEEP_RATE_LIMIT_BURST));
+
+    Router::new()
+        .fallback(hosts)
+        .wrap(AccessLog::new())
+        .wrap(RequestId::new())
+        .wrap(rate_limiter)
+}
+
+/// The handler for a site: its document root, or the built-in pages
+fn site_handler(site: &SiteConfig, pages: &Arc<dyn Handler>) -> Arc<dyn Handler> {
+    match &site.root {
+        Some(root) => Arc::new(StaticFiles::new(root)),
+        None => Arc::clone(pages),
+    }
+}
+
+/// Build the router with the built-in pages
+fn pages(stats: Arc<PoolStats>) -> Router {
+    let started_at = Instant::now();
+
+    let mut router = Router::new()
+        .get(ROOT_PATH, static_page(StatusCode::Ok, HELLO_HTML))
+        .get(SLEEP_PATH, static_page(StatusCode::Ok, SLEEP_COUNTER_HTML))
+        .get(SLEEP_WS_PATH, |request: &mut Request| websocket::upgrade(request, count_down))
+        .get(API_HEALTH_PATH, api::handler(move |()| health(started_at)))
+        .get(API_POOL_PATH, api::handler(move |()| Json(stats.snapshot())))
+        .fallback(static_page(StatusCode::NotFound, NOT_FOUND_404_HTML));
+
+    if let Ok(upstreams) = env::var(UPSTREAMS_ENV_VAR) {
+        let upstreams: Vec<&str> = upstreams.split(',').map(str::trim).filter(|u| !u.is_empty()).collect();
+        if !upstreams.is_empty() {
+            let proxy = Arc::new(Proxy::new(&upstreams).strip_prefix(PROXY_PATH));
+            Proxy::spawn_health_checks(&proxy, HEALTH_CHECK_INTERVAL);
+            router = router.mount(PROXY_PATH, move |request: &mut Request| proxy.forward(request));
+        }
+    }
+
+    router
+}
+
+/// Create a handler that serves the file at `filename` with `status`
+///
+/// The file is encoded according to the client's `Accept-Encoding`.
+pub fn static_page(status: StatusCode, filename: &'static str) -> impl Fn(&mut Request) -> Response + Send + Sync {
+    move |request: &mut Request| {
+        let accept_encoding = request.header("Accept-Encoding").unwrap_or_default();
+
+        Response::static_file(status, Path::new(filename), accept_encoding).unwrap_or_else(|error| {
+            eprintln!("Couldn't read '{}': {}", filename, error);
+            Response::new(StatusCode::InternalServerError)
+        })
+    }
+}
+
+/// Report that the server is up, with its version and how long it has been running
+fn health(started_at: Instant) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime_secs": started_at.elapsed().as_secs(),
+    }))
+}
+
+/// Push a countdown from `SLEEP_SECS` to the sleep page, one number per second
+///
+/// Sends `SLEEP_READY` at the end and closes the WebSocket.
+/// Stops early if the client goes away.
+fn count_down(mut socket: WebSocket<TcpStream>) {
+    for secs in (1..=SLEEP_SECS).rev() {
+        if socket.send_text(&secs.to_string()).is_err() {
+            return;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    if socket.send_text(SLEEP_READY).is_ok() {
+        let _ = socket.close(CLOSE_NORMAL, "");
+    }
+}
@@ -0,0 +1,210 @@
+//! The chunked transfer coding
+//!
+//! `ChunkedReader` decodes a chunked body while it is being read.
+//! `ReadChunks` goes the other way: it turns any reader into an iterator
+//! of chunks, which a `Response` can send with the chunked coding.
+
+use std::io::{self, BufRead, Read};
+
+use crate::request::MAX_HEAD_SIZE;
+
+/// The size of the chunks that `ReadChunks` produces
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The longest chunk size or trailer line that we accept, in bytes
+pub const MAX_LINE_SIZE: usize = 4 * 1024;
+
+/// A reader that decodes a body sent with the chunked transfer coding
+///
+/// Chunk extensions and trailers are skipped. Chunk size and trailer lines are limited
+/// to `MAX_LINE_SIZE`, and the trailer section as a whole to `MAX_HEAD_SIZE`.
+pub struct ChunkedReader<R: BufRead> {
+    inner: R,
+    /// Bytes left in the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(inner: R) -> ChunkedReader<R> {
+        ChunkedReader { inner, remaining: 0, done: false }
+    }
+
+    /// Read a line of at most `MAX_LINE_SIZE` bytes, including its line ending
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        (&mut self.inner).take(MAX_LINE_SIZE as u64 + 1).read_line(&mut line)?;
+
+        if line.len() > MAX_LINE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk line is too long."));
+        }
+
+        Ok(line)
+    }
+
+    /// Read the size line of the next chunk
+    fn next_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line()?;
+
+        let size = line.trim().split(';').next().unwrap_or_default();
+        u64::from_str_radix(size.trim(), 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Malformed chunk size."))
+    }
+
+    /// Skip the trailer section that follows the last chunk
+    fn skip_trailers(&mut self) -> io::Result<()> {
+        let mut size = 0;
+        loop {
+            let line = self.read_line()?;
+            if line.trim().is_empty() {
+                return Ok(());
+            }
+
+            size += line.len();
+            if size > MAX_HEAD_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Trailer section is too large."));
+            }
+        }
+    }
+
+    /// Skip the CRLF that ends a chunk's data
+    fn skip_crlf(&mut self) -> io::Result<()> {
+        let mut crlf = [0; 2];
+        self.inner.read_exact(&mut crlf)?;
+
+        if &crlf != b"\r\n" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Expected CRLF after chunk data."));
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.remaining = self.next_chunk_size()?;
+            if self.remaining == 0 {
+                self.done = true;
+                self.skip_trailers()?;
+                return Ok(0);
+            }
+        }
+
+        let max = buf.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Chunked body ended early."));
+        }
+
+        self.remaining -= read as u64;
+        if self.remaining == 0 {
+            self.skip_crlf()?;
+        }
+
+        Ok(read)
+    }
+}
+
+/// An iterator over the chunks of data that can be read from a reader
+///
+/// Stops at the end of the reader. An error while reading is yielded once, and ends
+/// the chunks, so that a body cut short isn't mistaken for a complete one.
+pub struct ReadChunks<R: Read> {
+    inner: R,
+    done: bool,
+}
+
+impl<R: Read> ReadChunks<R> {
+    pub fn new(inner: R) -> ReadChunks<R> {
+        ReadChunks { inner, done: false }
+    }
+}
+
+impl<R: Read> Iterator for ReadChunks<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+
+        let mut chunk = vec![0; CHUNK_SIZE];
+        match self.inner.read(&mut chunk) {
+            Ok(0) => {
+                self.done = true;
+                None
+            }
+            Ok(read) => {
+                chunk.truncate(read);
+                Some(Ok(chunk))
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_chunked() {
+        let raw = b"7\r\nHello, \r\ne;ext=1\r\nchunked world!\r\n0\r\nTrailer: x\r\n\r\nrest";
+        let mut cursor = Cursor::new(&raw[..]);
+
+        let mut body = String::new();
+        ChunkedReader::new(&mut cursor).read_to_string(&mut body).unwrap();
+        assert_eq!("Hello, chunked world!", body);
+
+        // The reader stops right after the body.
+        let mut rest = String::new();
+        cursor.read_to_string(&mut rest).unwrap();
+        assert_eq!("rest", rest);
+    }
+
+    #[test]
+    fn test_decode_malformed_chunk() {
+        let mut body = Vec::new();
+        let result = ChunkedReader::new(&b"zz\r\nHello\r\n0\r\n\r\n"[..]).read_to_end(&mut body);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let data = vec![7; CHUNK_SIZE + 10];
+        let chunks: Vec<Vec<u8>> = ReadChunks::new(&data[..]).map(Result::unwrap).collect();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!(CHUNK_SIZE, chunks[0].len());
+        assert_eq!(10, chunks[1].len());
+    }
+
+    #[test]
+    fn test_read_chunks_of_truncated_body() {
+        let raw = b"7\r\nHello, \r\n10\r\ncut";
+        let chunks: Vec<io::Result<Vec<u8>>> = ReadChunks::new(ChunkedReader::new(&raw[..])).collect();
+
+        assert_eq!(b"Hello, ", &chunks[0].as_ref().unwrap()[..]);
+        assert!(chunks.last().unwrap().is_err(), "Expected the truncation to be an error.");
+    }
+
+    #[test]
+    fn test_decode_too_long_lines() {
+        let mut body = Vec::new();
+        let size_line = format!("{}1\r\nx\r\n0\r\n\r\n", "0".repeat(MAX_LINE_SIZE));
+        assert!(ChunkedReader::new(size_line.as_bytes()).read_to_end(&mut body).is_err());
+
+        let trailer = format!("0\r\nX-Big: {}\r\n\r\n", "x".repeat(MAX_LINE_SIZE));
+        assert!(ChunkedReader::new(trailer.as_bytes()).read_to_end(&mut body).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Response compression
+//!
+//! Negotiates a content coding from the client's `Accept-Encoding` header
+//! and compresses response bodies with it.
+//!
+//! Supported codings are `gzip` and `deflate`.
+//! Bodies smaller than `MIN_COMPRESS_SIZE` are sent as they are,
+//! because compressing them costs more than it saves.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{
+    Compression,
+    write::{GzEncoder, ZlibEncoder},
+};
+
+/// Bodies shorter than this many bytes are not compressed
+pub const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// The extension of precompressed gzip siblings of static files
+pub const GZIP_EXTENSION: &str = "gz";
+
+/// A content coding that the server can apply to a response body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+    Identity,
+}
+
+impl Encoding {
+    /// The value to put in the `Content-Encoding` header, if any
+    pub fn header_value(&self) -> Option<&'static str> {
+        match self {
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Deflate => Some("deflate"),
+            Encoding::Identity => None,
+        }
+    }
+}
+
+/// Pick the best supported encoding from an `Accept-Encoding` header value
+///
+/// The coding with the highest quality value wins.
+/// On a tie, `gzip` is preferred over `deflate`.
+/// Codings with `q=0` are not acceptable.
+/// Falls back to `Encoding::Identity` if neither `gzip` nor `deflate` is acceptable,
+/// even if the client refused it, as RFC 9110 allows; see `identity_acceptable()`.
+pub fn negotiate(accept_encoding: &str) -> Encoding {
+    let gzip = quality(accept_encoding, "gzip").unwrap_or(0.0);
+    let deflate = quality(accept_encoding, "deflate").unwrap_or(0.0);
+
+    if gzip > 0.0 && gzip >= deflate {
+        Encoding::Gzip
+    } else if deflate > 0.0 {
+        Encoding::Deflate
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Check whether the client accepts a body without a content coding
+///
+/// It does unless it says `identity;q=0`, or `*;q=0` without an entry for `identity`.
+pub fn identity_acceptable(accept_encoding: &str) -> bool {
+    quality(accept_encoding, "identity").unwrap_or(1.0) > 0.0
+}
+
+/// Return the quality value the client assigned to `coding`
+///
+/// An explicit entry for the coding takes precedence over the `*` wildcard.
+/// `x-gzip` is treated as an alias for `gzip`.
+/// Returns `None` if the header mentions neither the coding nor `*`.
+fn quality(accept_encoding: &str, coding: &str) -> Option<f32> {
+    let mut wildcard = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim();
+
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let name = if name.eq_ignore_ascii_case("x-gzip") { "gzip" } else { name };
+
+        if name.eq_ignore_ascii_case(coding) {
+            return Some(q);
+        } else if name == "*" {
+            wildcard = Some(q);
+        }
+    }
+
+    wildcard
+}
+
+/// Compress `body` with `encoding`
+///
+/// Returns the body as it is for `Encoding::Identity`.
+pub fn compress(body: &[u8], encoding: Encoding) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Identity => Ok(body.to_vec()),
+    }
+}
+
+/// Compress `body` with `encoding` if it is large enough to be worth it
+///
+/// Returns the possibly compressed body and the encoding that was actually applied.
+pub fn compress_if_worth_it(body: Vec<u8>, encoding: Encoding) -> io::Result<(Vec<u8>, Encoding)> {
+    if encoding == Encoding::Identity || body.len() < MIN_COMPRESS_SIZE {
+        return Ok((body, Encoding::Identity));
+    }
+
+    Ok((compress(&body, encoding)?, encoding))
+}
+
+/// Encode `body` for a client with the given `Accept-Encoding`
+///
+/// Like `compress_if_worth_it()` with the negotiated encoding, except that
+/// small bodies are compressed too if the client refused `identity`.
+///
+/// Returns the possibly compressed body and the encoding that was actually applied.
+pub fn encode(body: Vec<u8>, accept_encoding: &str) -> io::Result<(Vec<u8>, Encoding)> {
+    let encoding = negotiate(accept_encoding);
+
+    if encoding != Encoding::Identity && !identity_acceptable(accept_encoding) {
+        return Ok((compress(&body, encoding)?, encoding));
+    }
+
+    compress_if_worth_it(body, encoding)
+}
+
+/// Return the path of the precompressed gzip sibling of `path`
+///
+/// For example, `templates/hello.html` becomes `templates/hello.html.gz`.
+pub fn precompressed_path(path: &Path) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(GZIP_EXTENSION);
+
+    PathBuf::from(sibling)
+}
+
+/// Read a static file and encode it for a client with the given `Accept-Encoding`
+///
+/// Serves the precompressed `.gz` sibling of the file if it exists,
+/// is at least as new as the file, and the client accepts gzip.
+/// A sibling that is older is stale, so it's ignored. Otherwise, reads the file
+/// and encodes it on the fly; see `encode()`.
+///
+/// Returns the body and the encoding that was applied to it.
+pub fn read_encoded(path: &Path, accept_encoding: &str) -> io::Result<(Vec<u8>, Encoding)> {
+    if quality(accept_encoding, "gzip").unwrap_or(0.0) > 0.0 && is_fresh(&precompressed_path(path), path) {
+        if let Ok(body) = fs::read(precompressed_path(path)) {
+            return Ok((body, Encoding::Gzip));
+        }
+    }
+
+    encode(fs::read(path)?, accept_encoding)
+}
+
+/// Check whether the file at `derived` was modified no earlier than the file at `source`
+///
+/// Returns `false` if either modification time can't be read.
+fn is_fresh(derived: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (modified(derived), modified(source)) {
+        (Ok(derived), Ok(source)) => derived >= source,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        time::{Duration, SystemTime},
+    };
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::Gzip, negotiate("gzip, deflate, br"));
+        assert_eq!(Encoding::Gzip, negotiate("deflate, gzip"));
+        assert_eq!(Encoding::Deflate, negotiate("gzip;q=0.5, deflate"));
+        assert_eq!(Encoding::Deflate, negotiate("gzip;q=0, *"));
+        assert_eq!(Encoding::Gzip, negotiate("*"));
+        assert_eq!(Encoding::Gzip, negotiate("x-gzip"));
+        assert_eq!(Encoding::Identity, negotiate("br, identity"));
+        assert_eq!(Encoding::Identity, negotiate(""));
+    }
+
+    #[test]
+    fn test_identity_acceptable() {
+        assert!(identity_acceptable(""));
+        assert!(identity_acceptable("gzip"));
+        assert!(identity_acceptable("*;q=0, identity"));
+        assert!(!identity_acceptable("gzip, identity;q=0"));
+        assert!(!identity_acceptable("gzip, *;q=0"));
+    }
+
+    #[test]
+    fn test_encode_small_bodies_when_identity_is_refused() {
+        let body = b"<h1>Hello!</h1>".to_vec();
+
+        assert_eq!(Encoding::Identity, encode(body.clone(), "gzip").unwrap().1);
+        let (encoded, encoding) = encode(body.clone(), "gzip, identity;q=0").unwrap();
+        assert_eq!(Encoding::Gzip, encoding);
+        let mut decoded = Vec::new();
+        GzDecoder::new(&encoded[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(body, decoded);
+
+        // Nothing acceptable is left, so the body is sent as it is.
+        assert_eq!((body.clone(), Encoding::Identity), encode(body, "identity;q=0").unwrap());
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let body = "Hi from Rust! ".repeat(200).into_bytes();
+
+        let mut gzip = Vec::new();
+        GzDecoder::new(&compress(&body, Encoding::Gzip).unwrap()[..]).read_to_end(&mut gzip).unwrap();
+        assert_eq!(body, gzip);
+
+        let mut deflate = Vec::new();
+        ZlibDecoder::new(&compress(&body, Encoding::Deflate).unwrap()[..]).read_to_end(&mut deflate).unwrap();
+        assert_eq!(body, deflate);
+    }
+
+    #[test]
+    fn test_small_bodies_are_not_compressed() {
+        let body = b"<h1>Hello!</h1>".to_vec();
+        let (encoded, encoding) = compress_if_worth_it(body.clone(), Encoding::Gzip).unwrap();
+
+        assert_eq!(Encoding::Identity, encoding);
+        assert_eq!(body, encoded);
+    }
+
+    #[test]
+    fn test_stale_precompressed_siblings_are_ignored() {
+        let dir = std::env::temp_dir().join(format!("hello-compression-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("page.html");
+        let body = "Hi from Rust! ".repeat(200).into_bytes();
+        fs::write(&path, &body).unwrap();
+        fs::write(precompressed_path(&path), b"precompressed").unwrap();
+
+        let now = SystemTime::now();
+        let set_modified = |path: &Path, time| fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+        set_modified(&path, now);
+        set_modified(&precompressed_path(&path), now);
+        assert_eq!((b"precompressed".to_vec(), Encoding::Gzip), read_encoded(&path, "gzip").unwrap());
+
+        set_modified(&precompressed_path(&path), now - Duration::from_secs(60));
+        let (encoded, encoding) = read_encoded(&path, "gzip").unwrap();
+        assert_eq!(Encoding::Gzip, encoding);
+        let mut decoded = Vec::new();
+        GzDecoder::new(&encoded[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(body, decoded, "Expected the stale sibling to be ignored.");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_precompressed_path() {
+        assert_eq!(
+            PathBuf::from("templates/hello.html.gz"),
+            precompressed_path(Path::new("templates/hello.html"))
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! The server configuration
+//!
+//! The configuration is a JSON file whose path is given in the `HELLO_CONFIG`
+//! environment variable. Without it, the server listens on `ADDRESS` with
+//! `NUM_CPU` workers and serves only the built-in pages. For example:
+//!
+//! ```json
+//! {
+//!     "address": "127.0.0.1:7878",
+//!     "threads": 4,
+//!     "sites": [
+//!         { "hosts": ["localhost", "127.0.0.1"], "default": true },
+//!         { "hosts": ["docs.localhost", "*.docs.localhost"], "root": "sites/docs" }
+//!     ]
+//! }
+//! ```
+//!
+//! A site with a `root` serves the static files under that document root.
+//! A site without one serves the built-in pages. The `default` site handles
+//! requests for hosts that no site lists; if no site is marked as the default,
+//! the built-in pages are.
+
+use std::{
+    env,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::constants::{ADDRESS, NUM_CPU};
+
+/// The environment variable that holds the path of the configuration file
+pub const CONFIG_ENV_VAR: &str = "HELLO_CONFIG";
+
+/// The configuration of the whole server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address to listen on, as `host:port`
+    pub address: String,
+    /// The number of worker threads
+    pub threads: usize,
+    pub sites: Vec<SiteConfig>,
+}
+
+/// The configuration of a site, which is served for one or more hosts
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SiteConfig {
+    /// Host names, without ports; `*.example.com` matches any subdomain of `example.com`
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// The directory to serve static files from; `None` serves the built-in pages
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    /// Whether the site handles requests for unknown hosts
+    #[serde(default)]
+    pub default: bool,
+}
+
+impl ServerConfig {
+    /// Load the configuration from the file named in `CONFIG_ENV_VAR`
+    ///
+    /// Returns the default configuration if the variable isn't set.
+    pub fn from_env() -> Result<ServerConfig, ConfigError> {
+        match env::var_os(CONFIG_ENV_VAR) {
+            Some(path) => ServerConfig::load(path),
+            None => Ok(ServerConfig::default()),
+        }
+    }
+
+    /// Load the configuration from the JSON file at `path`
+    ///
+    /// Relative document roots are resolved against the file's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<ServerConfig, ConfigError> {
+        let path = path.as_ref();
+        let mut config = ServerConfig::parse(&fs::read_to_string(path)?)?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for root in config.sites.iter_mut().filter_map(|site| site.root.as_mut()) {
+            if root.is_relative() {
+                *root = base.join(&*root);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Parse and validate a configuration
+    pub fn parse(json: &str) -> Result<ServerConfig, ConfigError> {
+        let config: ServerConfig = serde_json::from_str(json)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.threads == 0 {
+            return Err(ConfigError::Invalid("'threads' must be at least 1.".to_string()));
+        }
+
+        if self.sites.iter().filter(|site| site.default).count() > 1 {
+            return Err(ConfigError::Invalid("Only one site can be the default.".to_string()));
+        }
+
+        if let Some(site) = self.sites.iter().find(|site| site.hosts.is_empty() && !site.default) {
+            return Err(ConfigError::Invalid(format!("The site {site:?} has no hosts and isn't the default.")));
+        }
+
+        let mut hosts: Vec<String> = self.sites.iter().flat_map(|site| &site.hosts).map(|h| h.to_ascii_lowercase()).collect();
+        hosts.sort();
+        if let Some(pair) = hosts.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(ConfigError::Invalid(format!("The host '{}' is listed by more than one site.", pair[0])));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: ADDRESS.to_string(),
+            threads: NUM_CPU,
+            sites: Vec::new(),
+        }
+    }
+}
+
+/// An error that occurred while loading the configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Couldn't read the configuration: {error}"),
+            ConfigError::Json(error) => write!(f, "Malformed configuration: {error}"),
+            ConfigError::Invalid(reason) => write!(f, "Invalid configuration: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(error: serde_json::Error) -> Self {
+        ConfigError::Json(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = ServerConfig::parse("{}").unwrap();
+        assert_eq!(ServerConfig::default(), config);
+        assert_eq!(ADDRESS, config.address);
+        assert!(config.sites.is_empty());
+    }
+
+    #[test]
+    fn test_parse_sites() {
+        let config = ServerConfig::parse(
+            r#"{
+                "threads": 2,
+                "sites": [
+                    { "hosts": ["localhost"], "default": true },
+                    { "hosts": ["docs.localhost"], "root": "sites/docs" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(2, config.threads);
+        assert_eq!(2, config.sites.len());
+        assert!(config.sites[0].default);
+        assert_eq!(None, config.sites[0].root);
+        assert_eq!(Some(PathBuf::from("sites/docs")), config.sites[1].root);
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        assert!(matches!(ServerConfig::parse("{"), Err(ConfigError::Json(_))));
+        assert!(matches!(ServerConfig::parse(r#"{ "port": 80 }"#), Err(ConfigError::Json(_))));
+        assert!(matches!(ServerConfig::parse(r#"{ "threads": 0 }"#), Err(ConfigError::Invalid(_))));
+
+        let two_defaults = r#"{ "sites": [{ "default": true }, { "default": true }] }"#;
+        assert!(matches!(ServerConfig::parse(two_defaults), Err(ConfigError::Invalid(_))));
+
+        let no_hosts = r#"{ "sites": [{ "root": "www" }] }"#;
+        assert!(matches!(ServerConfig::parse(no_hosts), Err(ConfigError::Invalid(_))));
+
+        let duplicate = r#"{ "sites": [{ "hosts": ["a.test"] }, { "hosts": ["A.test"] }] }"#;
+        assert!(matches!(ServerConfig::parse(duplicate), Err(ConfigError::Invalid(_))));
+    }
+}
@@ -6,12 +6,29 @@ pub const NUM_CPU: usize = 4;
 pub const SLEEP_SECS: u64 = 5;
 
 pub const HELLO_HTML: &str = "templates/hello.html";
-pub const _SLEEP_HTML_COUNTER: &str = "templates/sleep_counter.html";
-pub const SLEEP_HTML: &str = "templates/sleep.html";
+pub const SLEEP_COUNTER_HTML: &str = "templates/sleep_counter.html";
+pub const _SLEEP_HTML: &str = "templates/sleep.html";
 pub const NOT_FOUND_404_HTML: &str = "templates/404.html";
 
-pub const GET_ROOT_URI: &str = "GET / HTTP/1.1";
-pub const GET_SLEEP_URI: &str = "GET /sleep HTTP/1.1";
+pub const _GET_ROOT_URI: &str = "GET / HTTP/1.1";
+pub const _GET_SLEEP_URI: &str = "GET /sleep HTTP/1.1";
 
-pub const STATUS_200_OK: &str = "HTTP/1.1 200 OK";
-pub const STATUS_404_NOT_FOUND: &str = "HTTP/1.1 404 NOT FOUND";
+pub const ROOT_PATH: &str = "/";
+pub const SLEEP_PATH: &str = "/sleep";
+pub const SLEEP_WS_PATH: &str = "/sleep/ws";
+pub const PROXY_PATH: &str = "/proxy";
+pub const API_HEALTH_PATH: &str = "/api/health";
+pub const API_POOL_PATH: &str = "/api/pool";
+
+/// A comma-separated list of `host:port` upstreams to proxy `PROXY_PATH` to
+pub const UPSTREAMS_ENV_VAR: &str = "HELLO_UPSTREAMS";
+
+pub const SLEEP_READY: &str = "Ready!";
+
+/// Requests per second that a client may send, and the burst it may send at once
+pub const RATE_LIMIT_PER_SEC: u32 = 20;
+pub const RATE_LIMIT_BURST: u32 = 40;
+/// The sleep page ties up a worker, so it is limited more strictly.
+/// A visit takes two requests: one for the page and one for its WebSocket.
+pub const SLEEP_RATE_LIMIT_PER_MIN: u32 = 12;
+pub const SLEEP_RATE_LIMIT_BURST: u32 = 6;
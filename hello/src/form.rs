@@ -0,0 +1,644 @@
+//! HTML form submissions
+//!
+//! `Form::read()` parses a request body sent as
+//! `application/x-www-form-urlencoded` or as `multipart/form-data`.
+//! Text fields are kept in memory; uploaded files are streamed to temporary
+//! files, so they don't need to fit in memory. `FormLimits` bounds both.
+//!
+//! A temporary file is deleted when its `UploadedFile` is dropped,
+//! unless the handler keeps it with `UploadedFile::persist()`.
+//!
+//! `Form` is also an extractor for `api::handler()`, with the default limits.
+
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fmt::{Display, Formatter},
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::api::{ApiError, FromRequest};
+use crate::headers::Headers;
+use crate::request::Request;
+use crate::response::StatusCode;
+
+pub const URLENCODED_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+pub const MULTIPART_CONTENT_TYPE: &str = "multipart/form-data";
+
+/// The longest boundary that RFC 2046 allows
+const MAX_BOUNDARY_LEN: usize = 70;
+
+/// The largest header section of a part, in bytes
+const MAX_PART_HEAD_SIZE: usize = 8 * 1024;
+
+/// How much of the body is read at a time
+const READ_SIZE: usize = 8 * 1024;
+
+/// Makes the names of temporary files unique within the process
+static NEXT_UPLOAD: AtomicU64 = AtomicU64::new(0);
+
+/// How many names to try for a temporary file before giving up
+const MAX_CREATE_ATTEMPTS: usize = 16;
+
+/// Limits on what a form may contain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormLimits {
+    /// The total size of the text fields, in bytes; for urlencoded forms, the size of the body
+    pub max_fields_size: u64,
+    /// The size of each uploaded file, in bytes
+    pub max_file_size: u64,
+    /// The number of uploaded files
+    pub max_files: usize,
+    /// Where uploaded files are stored until the handler is done with them
+    pub upload_dir: PathBuf,
+}
+
+impl Default for FormLimits {
+    fn default() -> Self {
+        FormLimits {
+            max_fields_size: 1024 * 1024,
+            max_file_size: 10 * 1024 * 1024,
+            max_files: 10,
+            upload_dir: env::temp_dir(),
+        }
+    }
+}
+
+/// The fields and files of a submitted form
+#[derive(Debug, Default)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+    files: Vec<UploadedFile>,
+}
+
+impl Form {
+    /// Read the form from the request's body, according to its `Content-Type`
+    pub fn read(request: &mut Request, limits: &FormLimits) -> Result<Form, FormError> {
+        let content_type = request.header("Content-Type").unwrap_or_default().to_string();
+        let (media_type, params) = parse_header_value(&content_type);
+
+        match media_type.to_ascii_lowercase().as_str() {
+            URLENCODED_CONTENT_TYPE => {
+                if request.content_length().is_some_and(|len| len > limits.max_fields_size) {
+                    return Err(FormError::TooLarge("The form is too large."));
+                }
+                let body = request.read_body(limits.max_fields_size).map_err(|error| match error.kind() {
+                    io::ErrorKind::FileTooLarge => FormError::TooLarge("The form is too large."),
+                    _ => FormError::Malformed("The form couldn't be read."),
+                })?;
+                Form::parse_urlencoded(&body)
+            }
+            MULTIPART_CONTENT_TYPE => {
+                let boundary = params
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+                    .map(|(_, boundary)| boundary.as_str())
+                    .filter(|boundary| (1..=MAX_BOUNDARY_LEN).contains(&boundary.len()))
+                    .ok_or(FormError::Malformed("Missing or invalid multipart boundary."))?;
+                Form::read_multipart(request.body(), boundary, limits)
+            }
+            _ => Err(FormError::UnsupportedMediaType),
+        }
+    }
+
+    /// Parse an `application/x-www-form-urlencoded` body
+    pub fn parse_urlencoded(body: &[u8]) -> Result<Form, FormError> {
+        let fields = serde_urlencoded::from_bytes(body).map_err(|_| FormError::Malformed("Malformed form."))?;
+        Ok(Form { fields, files: Vec::new() })
+    }
+
+    /// Read a `multipart/form-data` body delimited by `boundary`
+    pub fn read_multipart(body: &mut dyn Read, boundary: &str, limits: &FormLimits) -> Result<Form, FormError> {
+        let mut form = Form::default();
+        let mut reader = MultipartReader::new(body, boundary);
+        let mut fields_size = 0;
+
+        // Whatever precedes the first boundary is a preamble, which is ignored.
+        reader.copy_part(&mut io::sink(), u64::MAX)?;
+
+        while reader.next_part()? {
+            let head = reader.read_part_head()?;
+            let (_, disposition) = parse_header_value(head.get("Content-Disposition").unwrap_or_default());
+            let param = |name: &str| disposition.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone());
+
+            let name = param("name").ok_or(FormError::Malformed("A part has no field name."))?;
+
+            match param("filename") {
+                // Browsers send a file input without a file as a part with an empty file name.
+                Some(filename) if filename.is_empty() => {
+                    reader.copy_part(&mut io::sink(), limits.max_file_size)?;
+                }
+                Some(filename) => {
+                    if form.files.len() == limits.max_files {
+                        return Err(FormError::TooLarge("Too many files."));
+                    }
+
+                    let (mut file, stored) = UploadedFile::create(&limits.upload_dir, name, filename, head.get("Content-Type"))?;
+                    let mut writer = BufWriter::new(stored);
+                    file.size = reader.copy_part(&mut writer, limits.max_file_size)?;
+                    writer.flush()?;
+                    form.files.push(file);
+                }
+                None => {
+                    let mut value = Vec::new();
+                    fields_size += reader.copy_part(&mut value, limits.max_fields_size - fields_size)?;
+                    let value = String::from_utf8(value).map_err(|_| FormError::Malformed("A field isn't valid UTF-8."))?;
+                    form.fields.push((name, value));
+                }
+            }
+        }
+
+        Ok(form)
+    }
+
+    /// Return the value of the first field named `name`
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    /// All text fields, in the order in which they were sent
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// Return the first file uploaded with the field `name`
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|file| file.field == name)
+    }
+
+    /// All uploaded files, in the order in which they were sent
+    pub fn files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    /// Take the uploaded files out of the form, for example, to persist them
+    pub fn take_files(&mut self) -> Vec<UploadedFile> {
+        std::mem::take(&mut self.files)
+    }
+
+    /// Deserialize the text fields into `T`, as if they were a query string
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, FormError> {
+        serde_urlencoded::to_string(&self.fields)
+            .ok()
+            .and_then(|fields| serde_urlencoded::from_str(&fields).ok())
+            .ok_or(FormError::Invalid)
+    }
+}
+
+impl FromRequest for Form {
+    fn from_request(request: &mut Request) -> Result<Self, ApiError> {
+        Form::read(request, &FormLimits::default()).map_err(ApiError::from)
+    }
+}
+
+/// A file that was uploaded with a form, stored in a temporary file
+#[derive(Debug)]
+pub struct UploadedFile {
+    /// The name of the form field
+    pub field: String,
+    /// The name of the file on the client, as the client sent it; don't trust it as a path
+    pub filename: String,
+    pub content_type: String,
+    /// Where the file is stored
+    pub path: PathBuf,
+    /// The size of the file, in bytes
+    pub size: u64,
+    persisted: bool,
+}
+
+impl UploadedFile {
+    /// Create a new, empty temporary file in `dir`, and open it for writing
+    ///
+    /// The name ends in a random suffix, so that it can't be guessed, and the file must not
+    /// exist yet, so that a file or symlink planted under that name is never written through.
+    fn create(dir: &Path, field: String, filename: String, content_type: Option<&str>) -> io::Result<(UploadedFile, File)> {
+        let mut attempts = 0;
+        let (path, file) = loop {
+            let path = dir.join(format!("hello-upload-{}-{:016x}", process::id(), random_suffix()));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempts + 1 < MAX_CREATE_ATTEMPTS => attempts += 1,
+                Err(error) => return Err(error),
+            }
+        };
+
+        let upload = UploadedFile {
+            field,
+            filename,
+            content_type: content_type.unwrap_or("application/octet-stream").to_string(),
+            path,
+            size: 0,
+            persisted: false,
+        };
+        Ok((upload, file))
+    }
+
+    /// Open the stored file for reading
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Move the stored file to `path`, so that it is kept
+    ///
+    /// Falls back to copying when `path` is on another file system.
+    pub fn persist(mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if fs::rename(&self.path, path).is_err() {
+            fs::copy(&self.path, path)?;
+            let _ = fs::remove_file(&self.path);
+        }
+
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+/// An unpredictable number for the name of a temporary file
+///
+/// `RandomState` is seeded with random keys by the standard library; hashing a counter
+/// with them gives a different number each time, which can't be guessed from the outside.
+fn random_suffix() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(NEXT_UPLOAD.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Reads the parts of a multipart body, without buffering more than a few kilobytes
+struct MultipartReader<'a> {
+    reader: &'a mut dyn Read,
+    buf: Vec<u8>,
+    /// `CRLF--boundary`, which ends every part
+    delimiter: Vec<u8>,
+}
+
+impl<'a> MultipartReader<'a> {
+    fn new(reader: &'a mut dyn Read, boundary: &str) -> MultipartReader<'a> {
+        MultipartReader {
+            reader,
+            // The first delimiter may start the body, so it has no CRLF to match; add one.
+            buf: b"\r\n".to_vec(),
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+        }
+    }
+
+    /// Read more of the body into the buffer; returns `false` at the end of the body
+    fn fill(&mut self) -> io::Result<bool> {
+        let len = self.buf.len();
+        self.buf.resize(len + READ_SIZE, 0);
+        let read = self.reader.read(&mut self.buf[len..])?;
+        self.buf.truncate(len + read);
+        Ok(read > 0)
+    }
+
+    /// Make sure the buffer holds at least `len` bytes
+    fn fill_to(&mut self, len: usize) -> Result<(), FormError> {
+        while self.buf.len() < len {
+            if !self.fill()? {
+                return Err(FormError::Malformed("The multipart body ended early."));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the rest of the current part to `out`, and consume the delimiter that ends it
+    ///
+    /// Returns the number of bytes copied, which may not exceed `limit`.
+    fn copy_part(&mut self, out: &mut dyn Write, limit: u64) -> Result<u64, FormError> {
+        let mut copied = 0;
+        let mut write = |bytes: &[u8]| -> Result<(), FormError> {
+            copied += bytes.len() as u64;
+            if copied > limit {
+                return Err(FormError::TooLarge("A form field or file is too large."));
+            }
+            Ok(out.write_all(bytes)?)
+        };
+
+        loop {
+            if let Some(i) = find(&self.buf, &self.delimiter) {
+                write(&self.buf[..i])?;
+                self.buf.drain(..i + self.delimiter.len());
+                return Ok(copied);
+            }
+
+            // Keep enough to match a delimiter that is split between reads.
+            let keep = self.delimiter.len() - 1;
+            if self.buf.len() > keep {
+                let end = self.buf.len() - keep;
+                write(&self.buf[..end])?;
+                self.buf.drain(..end);
+            }
+
+            if !self.fill()? {
+                return Err(FormError::Malformed("The multipart body ended early."));
+            }
+        }
+    }
+
+    /// Consume what follows a delimiter; returns `false` after the closing delimiter
+    fn next_part(&mut self) -> Result<bool, FormError> {
+        self.fill_to(2)?;
+        if self.buf.starts_with(b"--") {
+            return Ok(false);
+        }
+
+        // Skip the transport padding and the line break that end the delimiter line.
+        let line = self.read_line()?;
+        if !line.iter().all(|b| b.is_ascii_whitespace()) {
+            return Err(FormError::Malformed("Malformed multipart boundary."));
+        }
+        Ok(true)
+    }
+
+    /// Read a line, including its line break
+    fn read_line(&mut self) -> Result<Vec<u8>, FormError> {
+        loop {
+            if let Some(i) = self.buf.iter().position(|&b| b == b'\n') {
+                return Ok(self.buf.drain(..=i).collect());
+            }
+            if self.buf.len() > MAX_PART_HEAD_SIZE {
+                return Err(FormError::TooLarge("A part's headers are too large."));
+            }
+            let len = self.buf.len();
+            self.fill_to(len + 1)?;
+        }
+    }
+
+    /// Read a part's headers
+    fn read_part_head(&mut self) -> Result<Headers, FormError> {
+        let mut headers = Headers::new();
+        let mut size = 0;
+
+        loop {
+            let line = self.read_line()?;
+            size += line.len();
+            if size > MAX_PART_HEAD_SIZE {
+                return Err(FormError::TooLarge("A part's headers are too large."));
+            }
+
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                return Ok(headers);
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                headers.append(name.trim(), value.trim());
+            }
+        }
+    }
+}
+
+/// Find the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Split a header value like `form-data; name="file"; filename="a.txt"` into its value and parameters
+///
+/// Quotes around parameter values are removed.
+fn parse_header_value(value: &str) -> (&str, Vec<(String, String)>) {
+    let mut parts = value.split(';');
+    let main = parts.next().unwrap_or_default().trim();
+
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            (name.trim().to_string(), value.replace("\\\"", "\""))
+        })
+        .collect();
+
+    (main, params)
+}
+
+/// An error that occurred while reading a form
+#[derive(Debug)]
+pub enum FormError {
+    /// The body isn't a form
+    UnsupportedMediaType,
+    /// The body breaks one of the `FormLimits`
+    TooLarge(&'static str),
+    Malformed(&'static str),
+    /// The fields don't have the expected shape
+    Invalid,
+    Io(io::Error),
+}
+
+impl FormError {
+    /// The status to respond with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedMediaType => StatusCode::UnsupportedMediaType,
+            FormError::TooLarge(_) => StatusCode::PayloadTooLarge,
+            FormError::Malformed(_) => StatusCode::BadRequest,
+            FormError::Invalid => StatusCode::UnprocessableEntity,
+            FormError::Io(error) if matches!(error.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData) => {
+                StatusCode::BadRequest
+            }
+            FormError::Io(_) => StatusCode::InternalServerError,
+        }
+    }
+}
+
+impl Display for FormError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType => {
+                write!(f, "Expected a body of type '{URLENCODED_CONTENT_TYPE}' or '{MULTIPART_CONTENT_TYPE}'.")
+            }
+            FormError::TooLarge(reason) | FormError::Malformed(reason) => write!(f, "{reason}"),
+            FormError::Invalid => write!(f, "The form's fields aren't valid."),
+            FormError::Io(error) => write!(f, "Couldn't read the form: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+impl From<io::Error> for FormError {
+    fn from(error: io::Error) -> Self {
+        FormError::Io(error)
+    }
+}
+
+impl From<FormError> for ApiError {
+    fn from(error: FormError) -> Self {
+        ApiError::new(error.status(), error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    const BOUNDARY: &str = "----hello1234";
+
+    fn request(content_type: &str, body: &[u8]) -> Request {
+        let mut raw = format!("POST / HTTP/1.1\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n", body.len())
+            .into_bytes();
+        raw.extend_from_slice(body);
+        Request::parse(&raw).unwrap()
+    }
+
+    fn multipart_body(file: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "preamble\r\n--{BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+             Hello, \"world\"!\r\n--{BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"upload\"; filename=\"notes.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(file);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\nepilogue").as_bytes());
+        body
+    }
+
+    fn limits(name: &str) -> FormLimits {
+        let upload_dir = env::temp_dir().join(format!("hello-form-{name}-{}", process::id()));
+        fs::create_dir_all(&upload_dir).unwrap();
+        FormLimits { upload_dir, ..FormLimits::default() }
+    }
+
+    #[test]
+    fn test_urlencoded() {
+        #[derive(Deserialize)]
+        struct Login {
+            user: String,
+            remember: bool,
+        }
+
+        let mut request = request(URLENCODED_CONTENT_TYPE, b"user=J%C3%BCrgen+S&remember=true&tag=a&tag=b");
+        let form = Form::read(&mut request, &FormLimits::default()).unwrap();
+
+        assert_eq!(Some("Jürgen S"), form.field("user"));
+        assert_eq!(4, form.fields().len());
+        assert!(form.files().is_empty());
+
+        let login: Login = form.deserialize().unwrap();
+        assert_eq!("Jürgen S", login.user);
+        assert!(login.remember);
+    }
+
+    #[test]
+    fn test_multipart() {
+        let limits = limits("multipart");
+        // Larger than a read, and containing a near-miss of the delimiter
+        let mut contents = vec![b'x'; READ_SIZE * 2];
+        contents.extend_from_slice(format!("\r\n--{}", &BOUNDARY[..5]).as_bytes());
+
+        let content_type = format!("{MULTIPART_CONTENT_TYPE}; boundary=\"{BOUNDARY}\"");
+        let mut request = request(&content_type, &multipart_body(&contents));
+        let mut form = Form::read(&mut request, &limits).unwrap();
+
+        assert_eq!(Some("Hello, \"world\"!"), form.field("title"));
+
+        let file = form.file("upload").unwrap();
+        assert_eq!("notes.txt", file.filename);
+        assert_eq!("text/plain", file.content_type);
+        assert_eq!(contents.len() as u64, file.size);
+        assert_eq!(contents, fs::read(&file.path).unwrap());
+
+        let temporary = file.path.clone();
+        let kept = limits.upload_dir.join("kept.txt");
+        form.take_files().remove(0).persist(&kept).unwrap();
+        assert!(!temporary.exists());
+        assert_eq!(contents, fs::read(&kept).unwrap());
+
+        drop(form);
+        fs::remove_dir_all(&limits.upload_dir).unwrap();
+    }
+
+    #[test]
+    fn test_temporary_files_are_removed() {
+        let limits = limits("removed");
+        let form = Form::read_multipart(&mut Cursor::new(multipart_body(b"data")), BOUNDARY, &limits).unwrap();
+        let path = form.file("upload").unwrap().path.clone();
+
+        assert!(path.exists());
+        drop(form);
+        assert!(!path.exists());
+
+        fs::remove_dir_all(&limits.upload_dir).unwrap();
+    }
+
+    #[test]
+    fn test_temporary_files_are_new() {
+        let limits = limits("new");
+        let (first, _) = UploadedFile::create(&limits.upload_dir, "a".to_string(), "a.txt".to_string(), None).unwrap();
+        let (second, _) = UploadedFile::create(&limits.upload_dir, "b".to_string(), "b.txt".to_string(), None).unwrap();
+        assert_ne!(first.path, second.path);
+
+        assert!(first.path.exists() && second.path.exists());
+
+        drop((first, second));
+        fs::remove_dir_all(&limits.upload_dir).unwrap();
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = FormLimits { max_file_size: 3, ..limits("limits") };
+        let result = Form::read_multipart(&mut Cursor::new(multipart_body(b"data")), BOUNDARY, &limits);
+        assert!(matches!(result, Err(FormError::TooLarge(_))));
+        // The partial upload has been removed.
+        assert_eq!(0, fs::read_dir(&limits.upload_dir).unwrap().count());
+
+        let limits = FormLimits { max_fields_size: 4, ..limits };
+        let mut too_large = request(URLENCODED_CONTENT_TYPE, b"title=hello");
+        assert_eq!(StatusCode::PayloadTooLarge, Form::read(&mut too_large, &limits).unwrap_err().status());
+
+        fs::remove_dir_all(&limits.upload_dir).unwrap();
+    }
+
+    #[test]
+    fn test_malformed() {
+        let limits = FormLimits::default();
+
+        let truncated = &multipart_body(b"data")[..60];
+        let result = Form::read_multipart(&mut Cursor::new(truncated), BOUNDARY, &limits);
+        assert!(matches!(result, Err(FormError::Malformed(_))));
+
+        let mut no_boundary = request(MULTIPART_CONTENT_TYPE, b"");
+        assert_eq!(StatusCode::BadRequest, Form::read(&mut no_boundary, &limits).unwrap_err().status());
+
+        let mut json = request("application/json", b"{}");
+        assert_eq!(StatusCode::UnsupportedMediaType, Form::read(&mut json, &limits).unwrap_err().status());
+
+        let raw = format!("POST /form HTTP/1.1\r\nContent-Type: {URLENCODED_CONTENT_TYPE}\r\nTransfer-Encoding: chunked\r\n\r\n10\r\ntitle=");
+        let mut truncated = Request::parse(raw.as_bytes()).unwrap();
+        assert_eq!(StatusCode::BadRequest, Form::read(&mut truncated, &limits).unwrap_err().status());
+    }
+
+    #[test]
+    fn test_empty_file_name_is_no_file() {
+        let limits = limits("no-file");
+        let body = String::from_utf8(multipart_body(b"")).unwrap().replace("filename=\"notes.txt\"", "filename=\"\"");
+
+        let form = Form::read_multipart(&mut Cursor::new(body), BOUNDARY, &limits).unwrap();
+        assert!(form.files().is_empty());
+        assert_eq!(Some("Hello, \"world\"!"), form.field("title"));
+        assert_eq!(0, fs::read_dir(&limits.upload_dir).unwrap().count());
+
+        fs::remove_dir_all(&limits.upload_dir).unwrap();
+    }
+}
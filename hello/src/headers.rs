@@ -0,0 +1,114 @@
+//! A map of HTTP header fields
+//!
+//! Header names are case-insensitive, but they are stored and written
+//! exactly as they were given. The insertion order is preserved.
+
+use std::fmt::{Display, Formatter};
+
+/// An ordered, case-insensitive multimap of header names to values
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Create an empty header map
+    pub fn new() -> Headers {
+        Headers { entries: Vec::new() }
+    }
+
+    /// Return the value of the first header named `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Return the values of all headers named `name`, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Check whether at least one header named `name` is present
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Set the header `name` to `value`, replacing any previous values
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    /// Add a value for the header `name`, keeping any previous values
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Remove all headers named `name`
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    /// Iterate over all `(name, value)` pairs in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Return the number of header fields
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether there are no header fields
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Display for Headers {
+    /// Formats the headers as they appear on the wire, each followed by CRLF
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in &self.entries {
+            write!(f, "{name}: {value}\r\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromIterator<(String, String)> for Headers {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        Headers { entries: iter.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Headers;
+
+    #[test]
+    fn test_insert_replaces_case_insensitively() {
+        let mut headers = Headers::new();
+        headers.insert("Content-Type", "text/plain");
+        headers.insert("content-type", "text/html");
+
+        assert_eq!(1, headers.len());
+        assert_eq!(Some("text/html"), headers.get("CONTENT-TYPE"));
+    }
+
+    #[test]
+    fn test_append_keeps_previous_values() {
+        let mut headers = Headers::new();
+        headers.append("Vary", "Accept-Encoding");
+        headers.append("Vary", "Origin");
+
+        assert_eq!(vec!["Accept-Encoding", "Origin"], headers.get_all("vary").collect::<Vec<_>>());
+        assert_eq!("Vary: Accept-Encoding\r\nVary: Origin\r\n", headers.to_string());
+    }
+}
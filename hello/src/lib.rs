@@ -4,16 +4,44 @@
 //!
 //! The pool can be used in a web server, as with our example here,
 //! but also for other purposes.
-
+//!
+//! It also contains the HTTP building blocks that our web server uses.
+
+pub mod api;
+pub mod app;
+pub mod chunked;
+pub mod compression;
+pub mod config;
+pub mod constants;
 mod error_consts;
+pub mod form;
+pub mod headers;
+pub mod middleware;
+pub mod proxy;
+pub mod rate_limit;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod server;
+pub mod static_files;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod vhost;
+pub mod websocket;
 
 use std::any::type_name;
 use std::fmt::{Debug, Display, Formatter};
 use std::{
-    sync::{Arc, Mutex, mpsc},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex, mpsc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
     thread,
 };
 
+use serde::Serialize;
+
 use error_consts::*;
 
 /// Create a `ThreadPool`
@@ -46,12 +74,10 @@ pub fn create_pool(size: usize) -> ThreadPool {
 
     // let pool = ThreadPool::build(size).unwrap();
 
-    let pool = match ThreadPool::build(size) {
+    match ThreadPool::build(size) {
         Ok(p) => p,
         Err(_) => ThreadPool::new(NUM_CPU),
-    };
-
-    pool
+    }
 }
 
 /// A thread pool that executes connections asynchronously
@@ -64,10 +90,11 @@ pub fn create_pool(size: usize) -> ThreadPool {
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
+    stats: Arc<PoolStats>,
 }
 
 /// The type of job that threads in the pool execute
-type Job = Box<dyn FnOnce() -> () + Send + 'static>;
+type Job = Box<dyn FnOnce() + Send + 'static>;
 
 impl ThreadPool {
 
@@ -100,10 +127,12 @@ impl ThreadPool {
     /// Sends the job to a worker down the channel.
     pub fn execute<F>(&self, f: F)
     where
-        F: FnOnce() -> () + Send + 'static,
+        F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
 
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+
         self.sender
             .as_ref().expect("Expected to extract sender from Some.")
             .send(job).expect("Expected to send a job.");
@@ -115,19 +144,33 @@ impl ThreadPool {
 
         let receiver = Arc::new(Mutex::new(receiver));
 
+        let stats = Arc::new(PoolStats {
+            size,
+            ..PoolStats::default()
+        });
+
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
             // Create threads and store them in the vector
-            // Share the receiver among the workers using Arc and Mutex
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            // Share the receiver and the stats among the workers using Arc and Mutex
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&stats)));
         }
 
         ThreadPool {
             workers,
             sender: Some(sender),
+            stats,
         }
     }
+
+    /// Get a handle to the pool's statistics
+    ///
+    /// The handle stays up to date as the pool executes jobs,
+    /// so it can be handed to whoever wants to monitor the pool.
+    pub fn stats(&self) -> Arc<PoolStats> {
+        Arc::clone(&self.stats)
+    }
 }
 
 impl Drop for ThreadPool {
@@ -144,7 +187,7 @@ impl Drop for ThreadPool {
             println!(" Shutting down worker {}.", worker.id);
 
             if let Some(thread) = worker.thread.take() {
-                thread.join().expect(format!("Expected to join the worker's {} thread.", worker.id).as_ref());
+                thread.join().unwrap_or_else(|_| panic!("Expected to join the worker's {} thread.", worker.id));
             }
         }
     }
@@ -161,7 +204,7 @@ impl Worker {
     ///
     /// Takes the worker's ID and a channel receiver through which it
     /// receives jobs that it needs to execute.
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, stats: Arc<PoolStats>) -> Worker {
         let builder = thread::Builder::new();
 
         // A thread loops forever waiting for jobs, but we have implemented a graceful shutdown.
@@ -174,13 +217,18 @@ impl Worker {
             loop {
                 let message =
                     receiver.lock()
-                        .expect(format!("Expected receiver for worker {} to acquire the lock.", id).as_ref())
+                        .unwrap_or_else(|_| panic!("Expected receiver for worker {} to acquire the lock.", id))
                         .recv();
 
                 match message {
                     Ok(job) => {
                         println!("Worker {id} got a job; executing.");
-                        job();
+                        let _active = ActiveJob::start(&stats);
+                        // A job that panics mustn't take its worker down with it,
+                        // or the pool would shrink, and `size` would be wrong.
+                        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            println!("Worker {id}'s job panicked; carrying on.");
+                        }
                     },
                     Err(_) => {
                         println!("  Worker {id} disconnected; shutting down.");
@@ -208,6 +256,65 @@ impl Worker {
     }
 }
 
+/// Counters that describe what a `ThreadPool` is doing
+///
+/// Obtained from `ThreadPool::stats()`.
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    size: usize,
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    completed: AtomicU64,
+}
+
+impl PoolStats {
+    /// Take a consistent-enough reading of the counters
+    pub fn snapshot(&self) -> PoolSnapshot {
+        PoolSnapshot {
+            size: self.size,
+            queued: self.queued.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A reading of a `ThreadPool`'s counters at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PoolSnapshot {
+    /// The number of worker threads
+    pub size: usize,
+    /// Jobs that wait for a worker
+    pub queued: usize,
+    /// Jobs that are being executed
+    pub active: usize,
+    /// Jobs that have finished, including those that panicked
+    pub completed: u64,
+}
+
+/// Marks a job as active for as long as it lives
+///
+/// Updates the counters on drop, so that they stay right even if the job panics.
+struct ActiveJob<'a> {
+    stats: &'a PoolStats,
+}
+
+impl<'a> ActiveJob<'a> {
+    fn start(stats: &'a PoolStats) -> ActiveJob<'a> {
+        stats.queued.fetch_sub(1, Ordering::Relaxed);
+        stats.active.fetch_add(1, Ordering::Relaxed);
+
+        ActiveJob { stats }
+    }
+}
+
+impl Drop for ActiveJob<'_> {
+    fn drop(&mut self) {
+        self.stats.active.fetch_sub(1, Ordering::Relaxed);
+        self.stats.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 #[derive(Clone)]
 pub struct PoolCreationError;
 
@@ -225,6 +332,8 @@ impl Display for PoolCreationError {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::mpsc;
+
     use super::ThreadPool;
 
     const NUM_CPU_TEST: usize = 4;
@@ -267,4 +376,42 @@ mod tests {
 
         pool.execute(|| {});
     }
+
+    #[test]
+    fn test_stats() {
+        let pool = ThreadPool::new(NUM_CPU_TEST);
+        let stats = pool.stats();
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..3 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(()).unwrap());
+        }
+        for _ in 0..3 {
+            receiver.recv().unwrap();
+        }
+        drop(pool);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(NUM_CPU_TEST, snapshot.size);
+        assert_eq!(0, snapshot.queued);
+        assert_eq!(0, snapshot.active);
+        assert_eq!(3, snapshot.completed);
+    }
+
+    #[test]
+    fn test_panicking_job_keeps_worker() {
+        let pool = ThreadPool::new(1);
+        let stats = pool.stats();
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(|| panic!("A job that panics"));
+        pool.execute(move || sender.send(()).unwrap());
+        receiver.recv().expect("Expected the worker to survive the panic.");
+        drop(pool);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(0, snapshot.active);
+        assert_eq!(2, snapshot.completed);
+    }
 }
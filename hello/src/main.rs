@@ -4,69 +4,57 @@
 //! - http://127.0.0.1:7878/
 //! - http://127.0.0.1:7878/sleep
 //! - http://127.0.0.1:7878/foo
-
-mod constants;
+//! - http://127.0.0.1:7878/api/health
+//! - http://127.0.0.1:7878/api/pool
+//!
+//! The sleep page counts down over a WebSocket at ws://127.0.0.1:7878/sleep/ws.
+//!
+//! The address, the number of threads, and the virtual hosts can be configured
+//! in a JSON file named by the `HELLO_CONFIG` environment variable;
+//! see `hello::config` for its format.
+//!
+//! If the `HELLO_UPSTREAMS` environment variable holds a comma-separated list of
+//! `host:port` upstreams, requests under http://127.0.0.1:7878/proxy are forwarded to them.
 
 use std::{
     fs,
     io::{prelude::*, BufReader},
     net::{TcpListener, TcpStream},
+    sync::Arc,
     thread,
     time::Duration,
 };
 
-use constants::*;
-use hello::create_pool;
+use hello::{
+    app,
+    config::ServerConfig,
+    constants::*,
+    create_pool,
+    response::{Response, StatusCode},
+    server,
+};
 
 fn main() {
     println!("Starting the server...");
 
-    let listener = TcpListener::bind(ADDRESS)
-        .expect(format!("Expected to bind TcpListener to '{}'.", ADDRESS).as_ref());
+    let config = ServerConfig::from_env().unwrap_or_else(|error| panic!("{}", error));
 
-    let pool = create_pool(NUM_CPU);
+    let listener = TcpListener::bind(&config.address)
+        .unwrap_or_else(|_| panic!("Expected to bind TcpListener to '{}'.", config.address));
 
-    println!("Waiting for requests...\n");
+    let pool = create_pool(config.threads);
+    let router = Arc::new(app::router(&config, pool.stats()));
 
-    // Practically an infinite loop, waiting for and serving client requests
-    for stream in listener.incoming() {
-        let stream = stream.expect("Expected a TcpStream.");
+    println!("Waiting for requests...\n");
 
-        pool.execute(|| {
-            handle_connection(stream);
-        });
-    }
+    server::serve(listener, &pool, router);
 
     println!("  Shutting down the server (the main thread).");
 }
 
-/// Seems to be more stable than the original implementation, which can be found below.
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer).expect("Expected to read into buffer.");
-
-    let (status_line, filename) = if buffer.starts_with(GET_ROOT_URI.as_ref()) {
-        (STATUS_200_OK, HELLO_HTML)
-    } else if buffer.starts_with(GET_SLEEP_URI.as_ref()) {
-        sleep(SLEEP_SECS);
-        (STATUS_200_OK, SLEEP_HTML)
-    } else {
-        (STATUS_404_NOT_FOUND, NOT_FOUND_404_HTML)
-    };
-
-    let contents = fs::read_to_string(filename)
-        .expect(format!("Expected to read '{}'.", filename).as_ref());
-    let length = contents.len();
-
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
-
-    stream.write_all(response.as_bytes()).expect("Expected to write to stream.");
-    stream.flush().expect("Expected to flush stream.");
-}
-
-fn sleep(secs: u64) {
-    // TODO: Implement counting down every second on the "sleep_counter" page that refreshes every second.
-    // TODO: At the end, use the regular "sleep" page; this is currently used in handle_connection() anyway.
+/// Used by the original implementation, which blocks while sleeping.
+/// The sleep page counts down with `count_down()` instead.
+fn _sleep(secs: u64) {
     thread::sleep(Duration::from_secs(secs));
 }
 
@@ -83,23 +71,25 @@ fn _handle_connection_original(mut stream: TcpStream) {
         .expect("Expected to read request line from buffer.")
         .expect("Expected to read line.");
 
-    let (status_line, filename) = match &request_line[..] {
-        GET_ROOT_URI => (STATUS_200_OK, HELLO_HTML),
-        GET_SLEEP_URI => {
-            sleep(SLEEP_SECS);
-            (STATUS_200_OK, SLEEP_HTML)
+    let (status, filename) = match &request_line[..] {
+        _GET_ROOT_URI => (StatusCode::Ok, HELLO_HTML),
+        _GET_SLEEP_URI => {
+            _sleep(SLEEP_SECS);
+            (StatusCode::Ok, _SLEEP_HTML)
         }
-        _ => (STATUS_404_NOT_FOUND, NOT_FOUND_404_HTML),
+        _ => (StatusCode::NotFound, NOT_FOUND_404_HTML),
     };
 
     let contents = fs::read_to_string(filename)
-        .expect(format!("Expected to read '{}'.", filename).as_ref());
-    let length = contents.len();
+        .unwrap_or_else(|_| panic!("Expected to read '{}'.", filename));
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+    let response = Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .text(contents)
+        .build();
 
-    stream.write_all(response.as_bytes()).expect("Expected to write to stream.");
-    stream.flush().expect("Expected to flush stream.");
+    response.write_to(&mut stream).expect("Expected to write to stream.");
 }
 
 /// This is for debugging purposes only.
@@ -0,0 +1,428 @@
+//! Middleware for cross-cutting behavior
+//!
+//! Middleware wraps every handler of a `Router`. It has two hooks:
+//! - `before` runs before the handler and may modify the request. It may also
+//!   short-circuit the request by returning a response, in which case the
+//!   handler isn't called.
+//! - `after` runs after the handler and may modify the response.
+//!
+//! This module contains the middleware that the server ships with:
+//! authentication, CORS, request IDs and access logging.
+
+use std::{
+    io::{self, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::request::Request;
+use crate::response::{Response, StatusCode, http_date};
+use crate::vhost::host_name;
+
+/// The header that carries the request ID
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Behavior that runs around every handler of a `Router`
+///
+/// Both hooks do nothing by default, so implementations only need
+/// to override the ones they use.
+pub trait Middleware: Send + Sync {
+    /// Called before the handler
+    ///
+    /// Returning a response skips the handler and the `before` hooks
+    /// of the middleware registered after this one.
+    fn before(&self, _request: &mut Request) -> Option<Response> {
+        None
+    }
+
+    /// Called after the handler, or after a `before` hook short-circuited
+    fn after(&self, _request: &Request, _response: &mut Response) {}
+}
+
+/// Tags every request and its response with a unique `X-Request-Id`
+///
+/// An ID sent by the client is kept, so that requests can be traced across services.
+pub struct RequestId {
+    prefix: String,
+    counter: AtomicU64,
+}
+
+impl RequestId {
+    /// Create the middleware
+    ///
+    /// IDs consist of a prefix that is unique to this instance and a sequence number.
+    pub fn new() -> RequestId {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+
+        RequestId {
+            prefix: format!("{:08x}", nanos as u32),
+            counter: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        RequestId::new()
+    }
+}
+
+impl Middleware for RequestId {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        if !request.headers.contains(REQUEST_ID_HEADER) {
+            let id = self.counter.fetch_add(1, Ordering::Relaxed);
+            request.headers.insert(REQUEST_ID_HEADER, format!("{}-{:06}", self.prefix, id));
+        }
+
+        None
+    }
+
+    fn after(&self, request: &Request, response: &mut Response) {
+        if let Some(id) = request.header(REQUEST_ID_HEADER) {
+            response.headers.insert(REQUEST_ID_HEADER, id);
+        }
+    }
+}
+
+/// Adds CORS headers to responses and answers preflight requests
+pub struct Cors {
+    allowed_origins: Vec<String>,
+    allowed_methods: Vec<String>,
+    allowed_headers: Vec<String>,
+    max_age: u64,
+}
+
+impl Cors {
+    /// Allow any origin to make `GET`, `POST` and `HEAD` requests
+    pub fn new() -> Cors {
+        Cors {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string(), "HEAD".to_string()],
+            allowed_headers: Vec::new(),
+            max_age: 600,
+        }
+    }
+
+    /// Only allow the given origin; can be called several times
+    ///
+    /// If it's never called, any origin is allowed.
+    pub fn allow_origin(mut self, origin: &str) -> Cors {
+        self.allowed_origins.push(origin.to_string());
+        self
+    }
+
+    /// Set the methods that cross-origin requests may use
+    pub fn allow_methods(mut self, methods: &[&str]) -> Cors {
+        self.allowed_methods = methods.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    /// Set the request headers that cross-origin requests may send
+    pub fn allow_headers(mut self, headers: &[&str]) -> Cors {
+        self.allowed_headers = headers.iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    /// Set how many seconds browsers may cache the result of a preflight request
+    pub fn max_age(mut self, secs: u64) -> Cors {
+        self.max_age = secs;
+        self
+    }
+
+    /// The value of `Access-Control-Allow-Origin` for `origin`, if it is allowed
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        if self.allowed_origins.is_empty() {
+            Some("*".to_string())
+        } else if self.allowed_origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        let is_preflight = request.method == "OPTIONS"
+            && request.headers.contains("Origin")
+            && request.headers.contains("Access-Control-Request-Method");
+        if !is_preflight {
+            return None;
+        }
+
+        let mut builder = Response::builder().status(StatusCode::NoContent).header("Vary", "Origin");
+        if let Some(origin) = request.header("Origin").and_then(|origin| self.allowed_origin(origin)) {
+            builder = builder
+                .header("Access-Control-Allow-Origin", origin)
+                .header("Access-Control-Allow-Methods", self.allowed_methods.join(", "))
+                .header("Access-Control-Max-Age", self.max_age.to_string());
+            if !self.allowed_headers.is_empty() {
+                builder = builder.header("Access-Control-Allow-Headers", self.allowed_headers.join(", "));
+            }
+        }
+
+        Some(builder.build())
+    }
+
+    fn after(&self, request: &Request, response: &mut Response) {
+        if let Some(origin) = request.header("Origin").and_then(|origin| self.allowed_origin(origin)) {
+            response.headers.insert("Access-Control-Allow-Origin", origin);
+            response.headers.append("Vary", "Origin");
+        }
+    }
+}
+
+/// How `Auth` checks credentials
+enum Credentials {
+    /// `Authorization: Basic <base64 of user:password>`
+    Basic { realm: String, encoded: String },
+    /// `Authorization: Bearer <token>`
+    Bearer { token: String },
+}
+
+/// Rejects requests under a path prefix that lack the right `Authorization` header
+///
+/// The prefix matches whole path segments, like `Router::mount()`:
+/// `/admin` covers `/admin` and `/admin/users`, but not `/administrator`.
+/// Credentials are compared in constant time, so that the time a rejection
+/// takes doesn't tell how much of them was right.
+///
+/// Rejected requests get `401 Unauthorized` with a `WWW-Authenticate` challenge.
+pub struct Auth {
+    path_prefix: String,
+    credentials: Credentials,
+}
+
+impl Auth {
+    /// Require HTTP Basic authentication with the given user and password
+    pub fn basic(path_prefix: &str, realm: &str, user: &str, password: &str) -> Auth {
+        Auth {
+            path_prefix: path_prefix.trim_end_matches('/').to_string(),
+            credentials: Credentials::Basic {
+                realm: realm.to_string(),
+                encoded: STANDARD.encode(format!("{user}:{password}")),
+            },
+        }
+    }
+
+    /// Require a bearer token
+    pub fn bearer(path_prefix: &str, token: &str) -> Auth {
+        Auth {
+            path_prefix: path_prefix.trim_end_matches('/').to_string(),
+            credentials: Credentials::Bearer { token: token.to_string() },
+        }
+    }
+}
+
+impl Middleware for Auth {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        let path = request.path();
+        let prefix = self.path_prefix.as_str();
+        if !(path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))) {
+            return None;
+        }
+
+        let authorization = request.header("Authorization").unwrap_or_default();
+        let (scheme, credentials) = authorization.split_once(' ').unwrap_or(("", ""));
+
+        let (authorized, challenge) = match &self.credentials {
+            Credentials::Basic { realm, encoded } => (
+                scheme.eq_ignore_ascii_case("Basic") && constant_time_eq(credentials.trim(), encoded),
+                format!("Basic realm=\"{realm}\""),
+            ),
+            Credentials::Bearer { token } => (
+                scheme.eq_ignore_ascii_case("Bearer") && constant_time_eq(credentials.trim(), token),
+                "Bearer".to_string(),
+            ),
+        };
+
+        if authorized {
+            None
+        } else {
+            Some(
+                Response::builder()
+                    .status(StatusCode::Unauthorized)
+                    .header("WWW-Authenticate", challenge)
+                    .text("Unauthorized.")
+                    .build(),
+            )
+        }
+    }
+}
+
+/// Compare `given` to `expected` in a time that depends only on their lengths
+///
+/// Every byte is compared, even after a mismatch, so that a client can't
+/// guess a secret byte by byte from how fast it's rejected.
+fn constant_time_eq(given: &str, expected: &str) -> bool {
+    if given.len() != expected.len() {
+        return false;
+    }
+
+    let difference = given.bytes().zip(expected.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b));
+    std::hint::black_box(difference) == 0
+}
+
+/// Writes one line per served request to a log
+///
+/// Lines look like in the Common Log Format, with the host that the request
+/// was for prepended, and the time it took to serve the request and
+/// the request ID appended:
+///
+/// `localhost 127.0.0.1 - - [Sun, 06 Nov 1994 08:49:37 GMT] "GET / HTTP/1.1" 200 225 3ms 5f3a1c2e-000001`
+pub struct AccessLog {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Log to the standard output
+    pub fn new() -> AccessLog {
+        AccessLog::to_writer(io::stdout())
+    }
+
+    /// Log to `writer`
+    pub fn to_writer(writer: impl Write + Send + 'static) -> AccessLog {
+        AccessLog { sink: Mutex::new(Box::new(writer)) }
+    }
+
+    /// Format the log line for a request and its response
+    pub fn format_entry(request: &Request, response: &Response) -> String {
+        let host = host_name(request).unwrap_or_else(|| "-".to_string());
+        let peer = request.peer_addr.map_or("-".to_string(), |addr| addr.ip().to_string());
+        let bytes = response.body.len().map_or("-".to_string(), |len| len.to_string());
+
+        format!(
+            "{host} {peer} - - [{}] \"{} {} {}\" {} {bytes} {}ms {}",
+            http_date(SystemTime::now()),
+            request.method,
+            request.target,
+            request.version,
+            response.status.code(),
+            request.received_at.elapsed().as_millis(),
+            request.header(REQUEST_ID_HEADER).unwrap_or("-"),
+        )
+    }
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        AccessLog::new()
+    }
+}
+
+impl Middleware for AccessLog {
+    fn after(&self, request: &Request, response: &mut Response) {
+        let entry = AccessLog::format_entry(request, response);
+
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = writeln!(sink, "{entry}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &str) -> Request {
+        Request::parse(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_request_id() {
+        let middleware = RequestId::new();
+
+        let mut first = request("GET / HTTP/1.1\r\n\r\n");
+        let mut second = request("GET / HTTP/1.1\r\n\r\n");
+        middleware.before(&mut first);
+        middleware.before(&mut second);
+        assert_ne!(first.header(REQUEST_ID_HEADER), second.header(REQUEST_ID_HEADER));
+
+        let mut response = Response::new(StatusCode::Ok);
+        middleware.after(&first, &mut response);
+        assert_eq!(first.header(REQUEST_ID_HEADER), response.headers.get(REQUEST_ID_HEADER));
+
+        let mut traced = request("GET / HTTP/1.1\r\nX-Request-Id: abc\r\n\r\n");
+        middleware.before(&mut traced);
+        assert_eq!(Some("abc"), traced.header(REQUEST_ID_HEADER));
+    }
+
+    #[test]
+    fn test_cors_preflight() {
+        let cors = Cors::new().allow_origin("http://example.com").allow_headers(&["Content-Type"]);
+
+        let mut preflight = request(
+            "OPTIONS / HTTP/1.1\r\nOrigin: http://example.com\r\nAccess-Control-Request-Method: POST\r\n\r\n",
+        );
+        let response = cors.before(&mut preflight).unwrap();
+        assert_eq!(StatusCode::NoContent, response.status);
+        assert_eq!(Some("http://example.com"), response.headers.get("Access-Control-Allow-Origin"));
+        assert_eq!(Some("Content-Type"), response.headers.get("Access-Control-Allow-Headers"));
+
+        let mut other = request("OPTIONS / HTTP/1.1\r\nOrigin: http://evil.com\r\nAccess-Control-Request-Method: POST\r\n\r\n");
+        let response = cors.before(&mut other).unwrap();
+        assert!(!response.headers.contains("Access-Control-Allow-Origin"));
+    }
+
+    #[test]
+    fn test_cors_simple_request() {
+        let cors = Cors::new();
+        let get = request("GET / HTTP/1.1\r\nOrigin: http://example.com\r\n\r\n");
+
+        let mut response = Response::new(StatusCode::Ok);
+        cors.after(&get, &mut response);
+        assert_eq!(Some("*"), response.headers.get("Access-Control-Allow-Origin"));
+    }
+
+    #[test]
+    fn test_auth_bearer() {
+        let auth = Auth::bearer("/admin", "secret");
+
+        assert!(auth.before(&mut request("GET /public HTTP/1.1\r\n\r\n")).is_none());
+        assert!(auth.before(&mut request("GET /admin HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n")).is_none());
+
+        let response = auth.before(&mut request("GET /admin/x HTTP/1.1\r\nAuthorization: Bearer nope\r\n\r\n")).unwrap();
+        assert_eq!(StatusCode::Unauthorized, response.status);
+        assert_eq!(Some("Bearer"), response.headers.get("WWW-Authenticate"));
+
+        assert!(auth.before(&mut request("GET /administrator HTTP/1.1\r\n\r\n")).is_none(), "Expected the prefix to match whole segments.");
+        assert!(auth.before(&mut request("GET /admin?x=1 HTTP/1.1\r\n\r\n")).is_some());
+        assert!(auth.before(&mut request("GET /admin HTTP/1.1\r\nAuthorization: Bearer secre\r\n\r\n")).is_some());
+        assert!(auth.before(&mut request("GET /admin HTTP/1.1\r\nAuthorization: Bearer secrets\r\n\r\n")).is_some());
+    }
+
+    #[test]
+    fn test_auth_basic() {
+        let auth = Auth::basic("/", "hello", "ferris", "crab");
+
+        // "ferris:crab" in base64
+        let ok = "GET / HTTP/1.1\r\nAuthorization: Basic ZmVycmlzOmNyYWI=\r\n\r\n";
+        assert!(auth.before(&mut request(ok)).is_none());
+
+        let response = auth.before(&mut request("GET / HTTP/1.1\r\n\r\n")).unwrap();
+        assert_eq!(Some("Basic realm=\"hello\""), response.headers.get("WWW-Authenticate"));
+    }
+
+    #[test]
+    fn test_access_log_entry() {
+        let mut request = request("GET /sleep HTTP/1.1\r\nHost: Example.com:7878\r\nX-Request-Id: abc\r\n\r\n");
+        request.peer_addr = Some("127.0.0.1:50000".parse().unwrap());
+        let response = Response::builder().status(StatusCode::NotFound).text("Oops!").build();
+
+        let entry = AccessLog::format_entry(&request, &response);
+        assert!(entry.starts_with("example.com 127.0.0.1 - - ["));
+        assert!(entry.contains("] \"GET /sleep HTTP/1.1\" 404 5 "));
+        assert!(entry.ends_with("ms abc"));
+    }
+}
@@ -0,0 +1,497 @@
+//! A reverse proxy
+//!
+//! `Proxy` forwards requests to one of several upstream servers, given as
+//! `host:port`, and relays their responses back to the client.
+//! Bodies are streamed in both directions, so they don't need to fit in memory.
+//!
+//! Upstreams are picked round-robin. An upstream that refuses a connection
+//! is marked unhealthy and skipped until a health check finds it up again.
+//! Health checks run periodically on a background thread started with
+//! `Proxy::spawn_health_checks()`. If all upstreams are unhealthy, they are
+//! all tried anyway, so that the proxy recovers even without health checks.
+//!
+//! The `Host` header is rewritten to the upstream's address, and the
+//! client's address and the original host are passed on in the
+//! `X-Forwarded-For`, `X-Forwarded-Host` and `X-Forwarded-Proto` headers.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::chunked::{ChunkedReader, ReadChunks};
+use crate::headers::Headers;
+use crate::request::{Request, is_chunked, read_head};
+use crate::response::{Body, Response, StatusCode};
+use crate::router::Handler;
+
+/// How long to wait for an upstream to accept a connection
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long to wait for an upstream to send or accept data
+pub const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often health checks run by default
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Headers that only apply to a single connection, and aren't forwarded
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// An upstream server
+#[derive(Debug)]
+struct Upstream {
+    addr: String,
+    healthy: AtomicBool,
+}
+
+/// A handler that forwards requests to upstream servers
+#[derive(Debug)]
+pub struct Proxy {
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+    strip_prefix: Option<String>,
+    health_check_path: Option<String>,
+}
+
+impl Proxy {
+    /// Create a proxy for the given upstreams, each given as `host:port`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upstreams` is empty.
+    pub fn new(upstreams: &[&str]) -> Proxy {
+        assert!(!upstreams.is_empty(), "Expected at least one upstream.");
+
+        Proxy {
+            upstreams: upstreams
+                .iter()
+                .map(|addr| Upstream { addr: addr.to_string(), healthy: AtomicBool::new(true) })
+                .collect(),
+            next: AtomicUsize::new(0),
+            strip_prefix: None,
+            health_check_path: None,
+        }
+    }
+
+    /// Remove `prefix` from request paths before forwarding them
+    ///
+    /// Useful when the proxy is mounted under a prefix, for example, `/proxy`,
+    /// that the upstreams don't know about.
+    pub fn strip_prefix(mut self, prefix: &str) -> Proxy {
+        self.strip_prefix = Some(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Check upstreams' health with `GET path`, expecting a `2xx` status
+    ///
+    /// Without this, a health check only checks that an upstream accepts connections.
+    pub fn health_check_path(mut self, path: &str) -> Proxy {
+        self.health_check_path = Some(path.to_string());
+        self
+    }
+
+    /// The addresses of the upstreams that are currently considered healthy
+    pub fn healthy_upstreams(&self) -> Vec<&str> {
+        self.upstreams
+            .iter()
+            .filter(|upstream| upstream.healthy.load(Ordering::Relaxed))
+            .map(|upstream| upstream.addr.as_str())
+            .collect()
+    }
+
+    /// Check the health of all upstreams now
+    pub fn check_health(&self) {
+        for upstream in &self.upstreams {
+            let healthy = self.probe(&upstream.addr).unwrap_or(false);
+            upstream.healthy.store(healthy, Ordering::Relaxed);
+        }
+    }
+
+    /// Check the health of all upstreams every `interval` on a background thread
+    ///
+    /// The thread stops once the proxy has been dropped.
+    pub fn spawn_health_checks(proxy: &Arc<Proxy>, interval: Duration) -> thread::JoinHandle<()> {
+        let proxy = Arc::downgrade(proxy);
+
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                match proxy.upgrade() {
+                    Some(proxy) => proxy.check_health(),
+                    None => break,
+                }
+            }
+        })
+    }
+
+    /// Forward `request` to an upstream and return its response
+    ///
+    /// Answers `502 Bad Gateway` if no upstream could be reached,
+    /// or if the upstream's response was malformed.
+    pub fn forward(&self, request: &mut Request) -> Response {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.upstreams.len();
+        let in_turn = (0..count).map(|i| &self.upstreams[(start + i) % count]);
+
+        let (healthy, unhealthy): (Vec<&Upstream>, Vec<&Upstream>) =
+            in_turn.partition(|upstream| upstream.healthy.load(Ordering::Relaxed));
+
+        for upstream in healthy.into_iter().chain(unhealthy) {
+            let stream = match connect(&upstream.addr) {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("Couldn't connect to upstream {}: {}", upstream.addr, error);
+                    upstream.healthy.store(false, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            upstream.healthy.store(true, Ordering::Relaxed);
+
+            // The request body may already be partially sent, so we can't retry on another upstream.
+            return self.exchange(upstream, stream, request).unwrap_or_else(|error| {
+                eprintln!("Upstream {} failed: {}", upstream.addr, error);
+                bad_gateway()
+            });
+        }
+
+        bad_gateway()
+    }
+
+    /// Send `request` over `stream` and read the upstream's response
+    fn exchange(&self, upstream: &Upstream, mut stream: TcpStream, request: &mut Request) -> io::Result<Response> {
+        let head = self.request_head(upstream, request);
+        stream.write_all(head.as_bytes())?;
+
+        if request.is_chunked() {
+            // A body that fails to read isn't ended with the last chunk, so the upstream
+            // sees it's incomplete once the connection is dropped.
+            for chunk in ReadChunks::new(request.body()) {
+                let chunk = chunk?;
+                write!(stream, "{:X}\r\n", chunk.len())?;
+                stream.write_all(&chunk)?;
+                stream.write_all(b"\r\n")?;
+            }
+            stream.write_all(b"0\r\n\r\n")?;
+        } else if request.content_length().is_some() {
+            io::copy(request.body(), &mut stream)?;
+        }
+        stream.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        read_response(&mut reader, &request.method).map(|(status, headers, framing)| {
+            let body = match framing {
+                Framing::Empty => Body::Empty,
+                Framing::Length(len) => Body::Reader(Box::new(reader), len),
+                Framing::Chunked => Body::Chunked(Box::new(ReadChunks::new(ChunkedReader::new(reader)))),
+                Framing::UntilClose => Body::Chunked(Box::new(ReadChunks::new(reader))),
+            };
+
+            Response { status, headers, body, upgrade: None }
+        })
+    }
+
+    /// Build the head of the request that is sent upstream
+    fn request_head(&self, upstream: &Upstream, request: &Request) -> String {
+        let target = match &self.strip_prefix {
+            Some(prefix) => match request.target.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.starts_with('/') => rest.to_string(),
+                Some(rest) => format!("/{rest}"),
+                None => request.target.clone(),
+            },
+            None => request.target.clone(),
+        };
+
+        let mut headers = end_to_end_headers(&request.headers);
+        headers.remove("Host");
+        headers.remove("Content-Length");
+        headers.insert("Host", upstream.addr.as_str());
+
+        if let Some(peer) = request.peer_addr {
+            let forwarded_for = match request.header("X-Forwarded-For") {
+                Some(previous) => format!("{previous}, {}", peer.ip()),
+                None => peer.ip().to_string(),
+            };
+            headers.insert("X-Forwarded-For", forwarded_for);
+        }
+        if let Some(host) = request.header("Host") {
+            headers.insert("X-Forwarded-Host", host);
+        }
+        headers.insert("X-Forwarded-Proto", "http");
+
+        if request.is_chunked() {
+            headers.insert("Transfer-Encoding", "chunked");
+        } else if let Some(len) = request.content_length() {
+            headers.insert("Content-Length", len.to_string());
+        }
+        headers.insert("Connection", "close");
+
+        format!("{} {} HTTP/1.1\r\n{}\r\n", request.method, target, headers)
+    }
+
+    /// Check whether the upstream at `addr` is up
+    fn probe(&self, addr: &str) -> io::Result<bool> {
+        let mut stream = connect(addr)?;
+
+        let Some(path) = &self.health_check_path else {
+            return Ok(true);
+        };
+
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n")?;
+        stream.flush()?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+
+        Ok(parse_status_line(&status_line).is_some_and(|status| (200..300).contains(&status.code())))
+    }
+}
+
+impl Handler for Proxy {
+    fn handle(&self, request: &mut Request) -> Response {
+        self.forward(request)
+    }
+}
+
+/// How the body of an upstream response is delimited
+enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+/// Read the status and headers of an upstream response, and find out how its body is framed
+fn read_response<R: BufRead>(reader: &mut R, method: &str) -> io::Result<(StatusCode, Headers, Framing)> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "Malformed upstream response.");
+
+    let (status_line, headers) = read_head(reader)?.ok_or_else(malformed)?;
+    let status = parse_status_line(&status_line).ok_or_else(malformed)?;
+
+    let framing = if method == "HEAD" || !status.allows_body() {
+        Framing::Empty
+    } else if is_chunked(&headers) {
+        Framing::Chunked
+    } else if let Some(len) = headers.get("Content-Length") {
+        Framing::Length(len.parse().map_err(|_| malformed())?)
+    } else {
+        Framing::UntilClose
+    };
+
+    let mut headers = end_to_end_headers(&headers);
+    headers.remove("Content-Length");
+
+    Ok((status, headers, framing))
+}
+
+/// Parse the status code out of a status line such as `HTTP/1.1 200 OK`
+fn parse_status_line(line: &str) -> Option<StatusCode> {
+    let mut parts = line.split_whitespace();
+    parts.next().filter(|version| version.starts_with("HTTP/"))?;
+
+    parts.next()?.parse().ok().map(StatusCode::from_code)
+}
+
+/// Copy `headers` without the hop-by-hop headers
+///
+/// Also drops the headers that the `Connection` header lists.
+fn end_to_end_headers(headers: &Headers) -> Headers {
+    let listed: Vec<String> = headers
+        .get_all("Connection")
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_string())
+        .collect();
+
+    headers
+        .iter()
+        .filter(|(name, _)| !HOP_BY_HOP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)))
+        .filter(|(name, _)| !listed.iter().any(|l| l.eq_ignore_ascii_case(name)))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Open a connection to `addr`, with timeouts
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, format!("Couldn't resolve '{addr}'."));
+
+    for socket_addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = error,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn bad_gateway() -> Response {
+    Response::builder()
+        .status(StatusCode::BadGateway)
+        .text("The upstream server couldn't be reached.")
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Start a stand-in upstream server that describes the requests it gets
+    ///
+    /// Requests for `/chunked` get a chunked response, and `/health` answers
+    /// `503 Service Unavailable` if the server was started as sick.
+    fn spawn_upstream(name: &'static str, sick: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = Request::read_from(reader).unwrap().unwrap();
+                let body = String::from_utf8(request.read_body(1024).unwrap()).unwrap();
+
+                let response = match request.path() {
+                    "/health" if sick => Response::new(StatusCode::ServiceUnavailable),
+                    "/chunked" => Response::builder()
+                        .stream(vec![name.as_bytes().to_vec(), b" streamed".to_vec()])
+                        .build(),
+                    _ => Response::builder()
+                        .status(StatusCode::Created)
+                        .header("Connection", "close, X-Secret")
+                        .header("X-Secret", "hop-by-hop")
+                        .text(format!(
+                            "{name} {} {} host={} xff={} xfh={} body={body}",
+                            request.method,
+                            request.target,
+                            request.header("Host").unwrap_or("-"),
+                            request.header("X-Forwarded-For").unwrap_or("-"),
+                            request.header("X-Forwarded-Host").unwrap_or("-"),
+                        ))
+                        .build(),
+                };
+                response.send(stream).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    /// An address on which nothing listens
+    fn dead_upstream() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    fn request(raw: &str) -> Request {
+        let mut request = Request::parse(raw.as_bytes()).unwrap();
+        request.peer_addr = Some("10.1.2.3:50000".parse().unwrap());
+        request
+    }
+
+    /// Write the response as it would go to the client, and decode its body
+    fn body_of(response: Response) -> String {
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let (head, body) = written.split_once("\r\n\r\n").unwrap();
+
+        if head.contains("Transfer-Encoding: chunked") {
+            let mut decoded = String::new();
+            io::Read::read_to_string(&mut ChunkedReader::new(body.as_bytes()), &mut decoded).unwrap();
+            decoded
+        } else {
+            body.to_string()
+        }
+    }
+
+    #[test]
+    fn test_forward_rewrites_headers_and_streams_body() {
+        let upstream = spawn_upstream("a", false);
+        let proxy = Proxy::new(&[&upstream]).strip_prefix("/proxy");
+
+        let mut request = request("POST /proxy/echo?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 4\r\n\r\nping");
+        let response = proxy.forward(&mut request);
+
+        assert_eq!(StatusCode::Created, response.status);
+        assert!(!response.headers.contains("X-Secret"));
+        assert_eq!(
+            format!("a POST /echo?x=1 host={upstream} xff=10.1.2.3 xfh=example.com body=ping"),
+            body_of(response)
+        );
+    }
+
+    #[test]
+    fn test_forward_chunked_both_ways() {
+        let upstream = spawn_upstream("a", false);
+        let proxy = Proxy::new(&[&upstream]);
+
+        let mut request = request("POST /chunked HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nping\r\n0\r\n\r\n");
+        let response = proxy.forward(&mut request);
+
+        assert!(matches!(response.body, Body::Chunked(_)));
+        assert_eq!("a streamed", body_of(response));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let proxy = Proxy::new(&[&spawn_upstream("a", false), &spawn_upstream("b", false)]);
+
+        let names: Vec<String> = (0..4)
+            .map(|_| body_of(proxy.forward(&mut request("GET / HTTP/1.1\r\n\r\n")))[..1].to_string())
+            .collect();
+
+        assert_eq!(vec!["a", "b", "a", "b"], names);
+    }
+
+    #[test]
+    fn test_failover_marks_upstream_unhealthy() {
+        let dead = dead_upstream();
+        let live = spawn_upstream("live", false);
+        let proxy = Proxy::new(&[&dead, &live]);
+
+        for _ in 0..3 {
+            let response = proxy.forward(&mut request("GET / HTTP/1.1\r\n\r\n"));
+            assert!(body_of(response).starts_with("live"));
+        }
+        assert_eq!(vec![live.as_str()], proxy.healthy_upstreams());
+    }
+
+    #[test]
+    fn test_all_upstreams_down() {
+        let proxy = Proxy::new(&[&dead_upstream()]);
+
+        assert_eq!(StatusCode::BadGateway, proxy.forward(&mut request("GET / HTTP/1.1\r\n\r\n")).status);
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let healthy = spawn_upstream("healthy", false);
+        let sick = spawn_upstream("sick", true);
+        let dead = dead_upstream();
+        let proxy = Proxy::new(&[&healthy, &sick, &dead]).health_check_path("/health");
+
+        proxy.check_health();
+        assert_eq!(vec![healthy.as_str()], proxy.healthy_upstreams());
+    }
+}
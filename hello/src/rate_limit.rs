@@ -0,0 +1,330 @@
+//! Per-client rate limiting
+//!
+//! Every client IP address gets a token bucket per rate-limited route.
+//! A request takes one token from its bucket; buckets refill at a steady
+//! rate up to their capacity, which allows short bursts.
+//! Requests that find their bucket empty are rejected with
+//! `429 Too Many Requests` and a `Retry-After` header.
+//!
+//! Buckets of clients that have been idle for a while, and that would have
+//! refilled by now, are expired periodically, so that the limiter's memory
+//! use stays bounded without handing out extra tokens.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+
+/// How often stale buckets are looked for
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a bucket must stay unused before it may be expired
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A rate limit: a sustained rate and a burst capacity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    capacity: f64,
+    per_sec: f64,
+}
+
+impl Limit {
+    /// Allow `requests` per second, with bursts of the same size
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests` is zero; a bucket that never refills can't say when to retry.
+    pub fn per_second(requests: u32) -> Limit {
+        assert!(requests > 0, "Expected a rate limit of at least one request.");
+        Limit { capacity: f64::from(requests), per_sec: f64::from(requests) }
+    }
+
+    /// Allow `requests` per minute, with bursts of the same size
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests` is zero; a bucket that never refills can't say when to retry.
+    pub fn per_minute(requests: u32) -> Limit {
+        assert!(requests > 0, "Expected a rate limit of at least one request.");
+        Limit { capacity: f64::from(requests), per_sec: f64::from(requests) / 60.0 }
+    }
+
+    /// Allow bursts of up to `requests`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests` is zero; a bucket that can't hold a token rejects every request.
+    pub fn burst(mut self, requests: u32) -> Limit {
+        assert!(requests > 0, "Expected a burst of at least one request.");
+        self.capacity = f64::from(requests);
+        self
+    }
+}
+
+/// A token bucket for one client and route
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_seen: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: &Limit, now: Instant) -> TokenBucket {
+        TokenBucket { tokens: limit.capacity, last_seen: now }
+    }
+
+    /// Whether the bucket would have refilled to its capacity at `now`
+    ///
+    /// A full bucket is no different from a new one, so it can be dropped.
+    fn is_full(&self, limit: &Limit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_seen).as_secs_f64();
+        self.tokens + elapsed * limit.per_sec >= limit.capacity
+    }
+
+    /// Refill the bucket and try to take a token
+    ///
+    /// Returns `Err` with the time until a token is available if the bucket is empty.
+    fn take(&mut self, limit: &Limit, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_seen).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_sec).min(limit.capacity);
+        self.last_seen = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.per_sec))
+        }
+    }
+}
+
+/// Middleware that limits how fast each client may send requests
+///
+/// Routes without a limit of their own share the default limit, if there is one.
+pub struct RateLimiter {
+    default: Option<Limit>,
+    routes: Vec<(String, Limit)>,
+    buckets: Mutex<HashMap<(IpAddr, String), TokenBucket>>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a limiter that applies `default` to all routes
+    pub fn new(default: Limit) -> RateLimiter {
+        RateLimiter {
+            default: Some(default),
+            ..RateLimiter::unlimited()
+        }
+    }
+
+    /// Create a limiter that only limits routes added with `route()`
+    pub fn unlimited() -> RateLimiter {
+        RateLimiter {
+            default: None,
+            routes: Vec::new(),
+            buckets: Mutex::new(HashMap::new()),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Apply `limit` to `path` and everything below it
+    ///
+    /// When several routes match, the one added first wins.
+    pub fn route(mut self, path: &str, limit: Limit) -> RateLimiter {
+        self.routes.push((path.trim_end_matches('/').to_string(), limit));
+        self
+    }
+
+    /// Find the limit of `route`, a key from `limit_for()`
+    fn route_limit(&self, route: &str) -> Option<&Limit> {
+        match self.routes.iter().find(|(path, _)| path == route) {
+            Some((_, limit)) => Some(limit),
+            None => self.default.as_ref().filter(|_| route.is_empty()),
+        }
+    }
+
+    /// Find the limit for `path`, and the key of the route it belongs to
+    fn limit_for(&self, path: &str) -> Option<(&str, &Limit)> {
+        let route = self.routes.iter().find(|(route, _)| {
+            path == route || path.strip_prefix(route.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        });
+
+        match route {
+            Some((route, limit)) => Some((route.as_str(), limit)),
+            None => self.default.as_ref().map(|limit| ("", limit)),
+        }
+    }
+
+    /// Check whether the client at `ip` may request `path` at `now`
+    ///
+    /// Returns `Err` with the time until the client may retry.
+    fn check(&self, ip: IpAddr, path: &str, now: Instant) -> Result<(), Duration> {
+        let Some((route, limit)) = self.limit_for(path) else {
+            return Ok(());
+        };
+
+        self.sweep_if_due(now);
+
+        let mut buckets = self.buckets.lock().expect("Expected the rate limiter's buckets to acquire the lock.");
+        buckets
+            .entry((ip, route.to_string()))
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .take(limit, now)
+    }
+
+    /// Expire stale buckets if `SWEEP_INTERVAL` has passed since the last sweep
+    fn sweep_if_due(&self, now: Instant) {
+        let mut last_sweep = self.last_sweep.lock().expect("Expected the rate limiter's sweep time to acquire the lock.");
+
+        if now.saturating_duration_since(*last_sweep) >= SWEEP_INTERVAL {
+            *last_sweep = now;
+            drop(last_sweep);
+            self.expire_stale(now);
+        }
+    }
+
+    /// Remove the buckets that haven't been used for `IDLE_TIMEOUT` or longer, and would be full by now
+    ///
+    /// A client whose bucket was removed gets a full bucket on its next request,
+    /// which is what it would have had anyway.
+    pub fn expire_stale(&self, now: Instant) {
+        let mut buckets = self.buckets.lock().expect("Expected the rate limiter's buckets to acquire the lock.");
+        buckets.retain(|(_, route), bucket| {
+            let idle = now.saturating_duration_since(bucket.last_seen) >= IDLE_TIMEOUT;
+            let full = self.route_limit(route).is_none_or(|limit| bucket.is_full(limit, now));
+            !(idle && full)
+        });
+    }
+
+    /// The number of buckets that are currently tracked
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.lock().map(|buckets| buckets.len()).unwrap_or(0)
+    }
+}
+
+impl Middleware for RateLimiter {
+    /// Requests without a known client address aren't limited.
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        let ip = request.peer_addr?.ip();
+
+        match self.check(ip, request.path(), Instant::now()) {
+            Ok(()) => None,
+            Err(retry_after) => {
+                // Round up, so that clients that obey the header don't get rejected again.
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+                Some(
+                    Response::builder()
+                        .status(StatusCode::TooManyRequests)
+                        .header("Retry-After", secs.max(1).to_string())
+                        .text("Too many requests; please slow down.")
+                        .build(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+    const OTHER_CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = RateLimiter::new(Limit::per_second(2));
+        let start = Instant::now();
+
+        assert!(limiter.check(CLIENT, "/", start).is_ok());
+        assert!(limiter.check(CLIENT, "/", start).is_ok());
+        let retry_after = limiter.check(CLIENT, "/", start).unwrap_err();
+        assert_eq!(Duration::from_millis(500), retry_after);
+
+        assert!(limiter.check(OTHER_CLIENT, "/", start).is_ok());
+        assert!(limiter.check(CLIENT, "/", start + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn test_per_route_limits() {
+        let limiter = RateLimiter::unlimited().route("/sleep", Limit::per_minute(1));
+        let now = Instant::now();
+
+        assert!(limiter.check(CLIENT, "/sleep", now).is_ok());
+        assert!(limiter.check(CLIENT, "/sleep/ws", now).is_err());
+        assert!(limiter.check(CLIENT, "/sleepy", now).is_ok());
+        assert!(limiter.check(CLIENT, "/", now).is_ok());
+        assert_eq!(1, limiter.bucket_count());
+    }
+
+    #[test]
+    fn test_expire_stale() {
+        let limiter = RateLimiter::new(Limit::per_second(1));
+        let start = Instant::now();
+
+        limiter.check(CLIENT, "/", start).unwrap();
+        limiter.check(OTHER_CLIENT, "/", start + IDLE_TIMEOUT / 2).unwrap();
+        assert_eq!(2, limiter.bucket_count());
+
+        limiter.expire_stale(start + IDLE_TIMEOUT);
+        assert_eq!(1, limiter.bucket_count());
+
+        // The periodic sweep expires the other client's bucket.
+        limiter.check(CLIENT, "/", start + IDLE_TIMEOUT * 2).unwrap();
+        assert_eq!(1, limiter.bucket_count());
+    }
+
+    #[test]
+    fn test_expire_only_full_buckets() {
+        let limiter = RateLimiter::new(Limit::per_minute(1).burst(100));
+        let start = Instant::now();
+
+        for _ in 0..100 {
+            limiter.check(CLIENT, "/", start).unwrap();
+        }
+
+        // Ten minutes refill ten of the hundred tokens; forgetting the bucket would refill them all.
+        limiter.expire_stale(start + IDLE_TIMEOUT);
+        assert_eq!(1, limiter.bucket_count());
+
+        limiter.expire_stale(start + Duration::from_secs(100 * 60));
+        assert_eq!(0, limiter.bucket_count());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_burst() {
+        let _limit = Limit::per_second(1).burst(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_per_second() {
+        let _limit = Limit::per_second(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_per_minute() {
+        let _limit = Limit::per_minute(0);
+    }
+
+    #[test]
+    fn test_too_many_requests_response() {
+        let limiter = RateLimiter::new(Limit::per_minute(1));
+        let mut request = Request::parse(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        request.peer_addr = Some("127.0.0.1:50000".parse().unwrap());
+
+        assert!(limiter.before(&mut request).is_none());
+        let response = limiter.before(&mut request).unwrap();
+        assert_eq!(StatusCode::TooManyRequests, response.status);
+        assert_eq!(Some("60"), response.headers.get("Retry-After"));
+    }
+}
@@ -0,0 +1,316 @@
+//! HTTP requests
+//!
+//! Parses the request line and the headers of an HTTP/1.x request.
+//! The body isn't read up front; it is exposed as a reader instead,
+//! so that handlers can stream large bodies.
+
+use std::{
+    fmt::{Debug, Formatter},
+    io::{self, BufRead, Cursor, Read},
+    net::SocketAddr,
+    time::Instant,
+};
+
+use crate::chunked::ChunkedReader;
+use crate::headers::Headers;
+
+/// The largest message head (start line and headers) that we accept, in bytes
+pub const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// An HTTP request
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Headers,
+    /// The address of the client, if the request came over a socket
+    pub peer_addr: Option<SocketAddr>,
+    /// When the request was parsed; used to measure how long it took to serve
+    pub received_at: Instant,
+    /// Parameters captured from the path by the router, for example, `id` in `/users/:id`
+    pub params: Vec<(String, String)>,
+    body: Box<dyn Read + Send>,
+}
+
+impl Request {
+    /// Parse a request from raw bytes read from a stream
+    ///
+    /// Whatever follows the head is the body.
+    /// Returns `None` if the request line is malformed.
+    /// Header lines without a colon are skipped.
+    pub fn parse(buffer: &[u8]) -> Option<Request> {
+        Request::read_from(Cursor::new(buffer.to_vec())).ok().flatten()
+    }
+
+    /// Read a request from `reader`
+    ///
+    /// Reads the head right away. The body is read lazily from `reader`,
+    /// as framed by the `Content-Length` or `Transfer-Encoding` header.
+    ///
+    /// Returns `Ok(None)` if the reader ends before a request starts,
+    /// which happens when a client closes an idle connection.
+    /// Fails with `io::ErrorKind::InvalidData` if the body's framing is ambiguous:
+    /// if `Content-Length` isn't a single number, or comes with `Transfer-Encoding`.
+    /// Otherwise, a proxy in front of us, or behind us, could read a different body than we do.
+    pub fn read_from<R: BufRead + Send + 'static>(mut reader: R) -> io::Result<Option<Request>> {
+        let Some((start_line, headers)) = read_head(&mut reader)? else {
+            return Ok(None);
+        };
+
+        let malformed = || io::Error::new(io::ErrorKind::InvalidData, "Malformed request line.");
+        let mut request_line = start_line.split_whitespace();
+        let method = request_line.next().ok_or_else(malformed)?.to_string();
+        let target = request_line.next().ok_or_else(malformed)?.to_string();
+        let version = request_line.next().ok_or_else(malformed)?.to_string();
+
+        let length = body_length(&headers)?;
+        let body: Box<dyn Read + Send> = if is_chunked(&headers) {
+            Box::new(ChunkedReader::new(reader))
+        } else {
+            Box::new(reader.take(length))
+        };
+
+        Ok(Some(Request {
+            method,
+            target,
+            version,
+            headers,
+            peer_addr: None,
+            received_at: Instant::now(),
+            params: Vec::new(),
+            body,
+        }))
+    }
+
+    /// The path part of the request target, without the query string
+    pub fn path(&self) -> &str {
+        self.target.split_once('?').map_or(&self.target, |(path, _)| path)
+    }
+
+    /// The query string of the request target, without the leading `?`
+    pub fn query(&self) -> Option<&str> {
+        self.target.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Return the value of the first header named `name`
+    ///
+    /// Header names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Return the value of the path parameter named `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The length of the body, if the client declared it
+    ///
+    /// Chunked bodies don't have a declared length.
+    pub fn content_length(&self) -> Option<u64> {
+        if self.is_chunked() {
+            return None;
+        }
+
+        self.header("Content-Length").and_then(|len| len.parse().ok())
+    }
+
+    /// Check whether the body is sent with the chunked transfer coding
+    pub fn is_chunked(&self) -> bool {
+        is_chunked(&self.headers)
+    }
+
+    /// The body, as a reader
+    pub fn body(&mut self) -> &mut (dyn Read + Send) {
+        &mut *self.body
+    }
+
+    /// Take the body out of the request, leaving an empty one
+    pub fn take_body(&mut self) -> Box<dyn Read + Send> {
+        std::mem::replace(&mut self.body, Box::new(io::empty()))
+    }
+
+    /// Replace the body
+    pub fn set_body(&mut self, body: impl Read + Send + 'static) {
+        self.body = Box::new(body);
+    }
+
+    /// Read the whole body into memory
+    ///
+    /// Fails with `io::ErrorKind::FileTooLarge` if the body is longer than `limit` bytes,
+    /// so that callers can tell that apart from a body that couldn't be read.
+    pub fn read_body(&mut self, limit: u64) -> io::Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.body().take(limit + 1).read_to_end(&mut body)?;
+
+        if body.len() as u64 > limit {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "Request body is too large."));
+        }
+
+        Ok(body)
+    }
+}
+
+impl Debug for Request {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("target", &self.target)
+            .field("version", &self.version)
+            .field("headers", &self.headers)
+            .field("peer_addr", &self.peer_addr)
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read the head of an HTTP message: the start line and the headers
+///
+/// Works for both requests and responses.
+/// Returns `Ok(None)` if the reader ends before the message starts.
+pub fn read_head<R: BufRead>(reader: &mut R) -> io::Result<Option<(String, Headers)>> {
+    let mut head = Vec::new();
+    loop {
+        let read = reader.take((MAX_HEAD_SIZE - head.len()) as u64 + 1).read_until(b'\n', &mut head)?;
+        if read == 0 || head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+        if head.len() > MAX_HEAD_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Message head is too large."));
+        }
+    }
+
+    if head.is_empty() {
+        return Ok(None);
+    }
+
+    let text = String::from_utf8_lossy(&head);
+    let mut lines = text.lines();
+    let start_line = lines.next().unwrap_or_default().to_string();
+
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    Ok(Some((start_line, headers)))
+}
+
+/// The length of the body from the `Content-Length` headers, or 0 without them
+///
+/// Several `Content-Length` headers are allowed only if they agree,
+/// and none are allowed with `Transfer-Encoding`.
+fn body_length(headers: &Headers) -> io::Result<u64> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut lengths = headers.get_all("Content-Length").peekable();
+    if lengths.peek().is_some() && headers.get("Transfer-Encoding").is_some() {
+        return Err(invalid("Request has both Content-Length and Transfer-Encoding."));
+    }
+
+    let mut length = None;
+    for value in lengths {
+        if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid("Invalid Content-Length."));
+        }
+        let value = value.parse().map_err(|_| invalid("Invalid Content-Length."))?;
+        if length.is_some_and(|length| length != value) {
+            return Err(invalid("Conflicting Content-Length headers."));
+        }
+        length = Some(value);
+    }
+
+    Ok(length.unwrap_or(0))
+}
+
+/// Check whether the last transfer coding in `headers` is `chunked`
+pub fn is_chunked(headers: &Headers) -> bool {
+    headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .last()
+        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_line_and_headers() {
+        let raw = b"GET /sleep HTTP/1.1\r\nHost: 127.0.0.1:7878\r\nAccept-Encoding: gzip, deflate\r\n\r\n";
+        let request = Request::parse(raw).unwrap();
+
+        assert_eq!("GET", request.method);
+        assert_eq!("/sleep", request.target);
+        assert_eq!("HTTP/1.1", request.version);
+        assert_eq!(Some("gzip, deflate"), request.header("accept-encoding"));
+        assert_eq!(None, request.header("Cookie"));
+    }
+
+    #[test]
+    fn test_path_and_query() {
+        let request = Request::parse(b"GET /search?q=rust&page=2 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!("/search", request.path());
+        assert_eq!(Some("q=rust&page=2"), request.query());
+
+        let request = Request::parse(b"GET /sleep HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!("/sleep", request.path());
+        assert_eq!(None, request.query());
+    }
+
+    #[test]
+    fn test_parse_malformed_request_line() {
+        assert!(Request::parse(b"GET\r\n\r\n").is_none());
+        assert!(Request::parse(b"").is_none());
+    }
+
+    #[test]
+    fn test_body_with_content_length() {
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello, and more";
+        let mut request = Request::parse(raw).unwrap();
+
+        assert_eq!(Some(5), request.content_length());
+        assert_eq!(b"hello".to_vec(), request.read_body(1024).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_content_length() {
+        for length in ["abc", "-1", "5, 6", "+5", ""] {
+            let raw = format!("POST / HTTP/1.1\r\nContent-Length: {length}\r\n\r\nhello, and more");
+            assert!(Request::read_from(Cursor::new(raw.into_bytes())).is_err(), "Expected '{length}' to be rejected.");
+        }
+
+        assert!(Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello, and more").is_none());
+        assert!(Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello").is_some());
+    }
+
+    #[test]
+    fn test_content_length_with_transfer_encoding() {
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        assert!(Request::read_from(Cursor::new(raw.to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let mut request = Request::parse(raw).unwrap();
+
+        assert!(request.is_chunked());
+        assert_eq!(None, request.content_length());
+        assert_eq!(b"hello".to_vec(), request.read_body(1024).unwrap());
+    }
+
+    #[test]
+    fn test_body_limit() {
+        let mut request = Request::parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(io::ErrorKind::FileTooLarge, request.read_body(4).unwrap_err().kind());
+    }
+
+    #[test]
+    fn test_head_too_large() {
+        let raw = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "x".repeat(MAX_HEAD_SIZE));
+        assert!(Request::read_from(Cursor::new(raw.into_bytes())).is_err());
+    }
+}
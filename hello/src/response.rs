@@ -0,0 +1,605 @@
+//! HTTP responses
+//!
+//! A `Response` consists of a status, headers and a body.
+//! Responses are usually put together with a `ResponseBuilder`,
+//! which is obtained from `Response::builder()`.
+//!
+//! The body can come from bytes, a string, a file, or an iterator of chunks.
+//! Files are streamed, so they don't need to fit in memory.
+//! Iterators are sent with the chunked transfer encoding,
+//! because their length isn't known in advance.
+//!
+//! The `Date` and `Server` headers are added automatically
+//! when the response is written, unless they have already been set.
+//!
+//! A response can also carry an upgrade handler, which takes over
+//! the connection after the response has been sent. This is how
+//! WebSocket connections are served.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::compression::{self, Encoding};
+use crate::headers::Headers;
+
+/// The value of the `Server` header
+pub const SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The HTTP version that the server speaks
+pub const HTTP_VERSION: &str = "HTTP/1.1";
+
+/// An HTTP status code
+///
+/// Codes that don't have a variant of their own, for example, those relayed
+/// from an upstream server, are represented by `StatusCode::Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusCode {
+    SwitchingProtocols,
+    Ok,
+    Created,
+    Accepted,
+    NoContent,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    UnprocessableEntity,
+    TooManyRequests,
+    InternalServerError,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    Other(u16),
+}
+
+impl StatusCode {
+    /// Return the status for a numeric code
+    pub fn from_code(code: u16) -> StatusCode {
+        match code {
+            101 => StatusCode::SwitchingProtocols,
+            200 => StatusCode::Ok,
+            201 => StatusCode::Created,
+            202 => StatusCode::Accepted,
+            204 => StatusCode::NoContent,
+            301 => StatusCode::MovedPermanently,
+            302 => StatusCode::Found,
+            303 => StatusCode::SeeOther,
+            304 => StatusCode::NotModified,
+            307 => StatusCode::TemporaryRedirect,
+            308 => StatusCode::PermanentRedirect,
+            400 => StatusCode::BadRequest,
+            401 => StatusCode::Unauthorized,
+            403 => StatusCode::Forbidden,
+            404 => StatusCode::NotFound,
+            405 => StatusCode::MethodNotAllowed,
+            409 => StatusCode::Conflict,
+            413 => StatusCode::PayloadTooLarge,
+            415 => StatusCode::UnsupportedMediaType,
+            422 => StatusCode::UnprocessableEntity,
+            429 => StatusCode::TooManyRequests,
+            500 => StatusCode::InternalServerError,
+            502 => StatusCode::BadGateway,
+            503 => StatusCode::ServiceUnavailable,
+            504 => StatusCode::GatewayTimeout,
+            code => StatusCode::Other(code),
+        }
+    }
+
+    /// The numeric status code
+    pub fn code(&self) -> u16 {
+        match self {
+            StatusCode::SwitchingProtocols => 101,
+            StatusCode::Ok => 200,
+            StatusCode::Created => 201,
+            StatusCode::Accepted => 202,
+            StatusCode::NoContent => 204,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::Found => 302,
+            StatusCode::SeeOther => 303,
+            StatusCode::NotModified => 304,
+            StatusCode::TemporaryRedirect => 307,
+            StatusCode::PermanentRedirect => 308,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::Forbidden => 403,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::Conflict => 409,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::UnsupportedMediaType => 415,
+            StatusCode::UnprocessableEntity => 422,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::InternalServerError => 500,
+            StatusCode::BadGateway => 502,
+            StatusCode::ServiceUnavailable => 503,
+            StatusCode::GatewayTimeout => 504,
+            StatusCode::Other(code) => *code,
+        }
+    }
+
+    /// The reason phrase that goes with the status code
+    ///
+    /// Empty for `StatusCode::Other`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            StatusCode::SwitchingProtocols => "Switching Protocols",
+            StatusCode::Ok => "OK",
+            StatusCode::Created => "Created",
+            StatusCode::Accepted => "Accepted",
+            StatusCode::NoContent => "No Content",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::Found => "Found",
+            StatusCode::SeeOther => "See Other",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::TemporaryRedirect => "Temporary Redirect",
+            StatusCode::PermanentRedirect => "Permanent Redirect",
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::Forbidden => "Forbidden",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::Conflict => "Conflict",
+            StatusCode::PayloadTooLarge => "Payload Too Large",
+            StatusCode::UnsupportedMediaType => "Unsupported Media Type",
+            StatusCode::UnprocessableEntity => "Unprocessable Entity",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::BadGateway => "Bad Gateway",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+            StatusCode::GatewayTimeout => "Gateway Timeout",
+            StatusCode::Other(_) => "",
+        }
+    }
+
+    /// Whether responses with this status may carry a body
+    pub fn allows_body(&self) -> bool {
+        let code = self.code();
+        !((100..200).contains(&code) || code == 204 || code == 304)
+    }
+}
+
+impl Display for StatusCode {
+    /// Formats the status as in a status line, for example, `404 Not Found`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// The body of a response
+pub enum Body {
+    /// No body at all
+    Empty,
+    /// A body that is fully in memory
+    Bytes(Vec<u8>),
+    /// A file of the given length, which is streamed when the response is written
+    File(File, u64),
+    /// A reader of the given length, which is streamed when the response is written
+    Reader(Box<dyn Read + Send>, u64),
+    /// A body of unknown length, which is sent with the chunked transfer encoding
+    ///
+    /// If a chunk is an error, the body is cut off there, without the last chunk,
+    /// so that the client can tell that it's incomplete.
+    Chunked(Box<dyn Iterator<Item = io::Result<Vec<u8>>> + Send>),
+}
+
+impl Body {
+    /// The length of the body, if it is known in advance
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Empty => Some(0),
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(_, len) | Body::Reader(_, len) => Some(*len),
+            Body::Chunked(_) => None,
+        }
+    }
+
+    /// Check whether the body is known to be empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Body::Empty => write!(f, "Body::Empty"),
+            Body::Bytes(bytes) => write!(f, "Body::Bytes({} bytes)", bytes.len()),
+            Body::File(_, len) => write!(f, "Body::File({len} bytes)"),
+            Body::Reader(_, len) => write!(f, "Body::Reader({len} bytes)"),
+            Body::Chunked(_) => write!(f, "Body::Chunked"),
+        }
+    }
+}
+
+/// A handler that takes over the connection after a response has been sent
+pub struct Upgrade(Box<dyn FnOnce(TcpStream) + Send>);
+
+impl Upgrade {
+    /// Hand the connection over to the upgrade handler
+    pub fn run(self, stream: TcpStream) {
+        (self.0)(stream)
+    }
+}
+
+impl std::fmt::Debug for Upgrade {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upgrade")
+    }
+}
+
+/// An HTTP response
+#[derive(Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Body,
+    pub upgrade: Option<Upgrade>,
+}
+
+impl Response {
+    /// Create a response with the given status, no headers and an empty body
+    pub fn new(status: StatusCode) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::Empty,
+            upgrade: None,
+        }
+    }
+
+    /// Start building a response with the `200 OK` status
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            response: Response::new(StatusCode::Ok),
+        }
+    }
+
+    /// Build a response that serves a static file
+    ///
+    /// The body is encoded for a client with the given `Accept-Encoding`.
+    /// See `compression::read_encoded()` for details.
+    pub fn static_file(status: StatusCode, path: &Path, accept_encoding: &str) -> io::Result<Response> {
+        let (body, encoding) = compression::read_encoded(path, accept_encoding)?;
+
+        let mut builder = Response::builder()
+            .status(status)
+            .header("Content-Type", content_type(path))
+            .header("Vary", "Accept-Encoding")
+            .bytes(body);
+
+        if let Some(content_encoding) = encoding.header_value() {
+            builder = builder.header("Content-Encoding", content_encoding);
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Compress an in-memory body for a client with the given `Accept-Encoding`
+    ///
+    /// Bodies that are streamed or already encoded are left alone, and so are small ones,
+    /// unless the client refused `identity`. See `compression::encode()`.
+    pub fn compressed(mut self, accept_encoding: &str) -> io::Result<Response> {
+        if self.headers.contains("Content-Encoding") {
+            return Ok(self);
+        }
+
+        if let Body::Bytes(bytes) = self.body {
+            let (bytes, encoding) = compression::encode(bytes, accept_encoding)?;
+            self.body = Body::Bytes(bytes);
+            self.headers.append("Vary", "Accept-Encoding");
+            if let Some(content_encoding) = encoding.header_value() {
+                self.headers.insert("Content-Encoding", content_encoding);
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// The encoding that was applied to the body, according to the headers
+    pub fn encoding(&self) -> Encoding {
+        match self.headers.get("Content-Encoding") {
+            Some("gzip") => Encoding::Gzip,
+            Some("deflate") => Encoding::Deflate,
+            _ => Encoding::Identity,
+        }
+    }
+
+    /// Write the response to `writer`
+    ///
+    /// Adds the default `Date` and `Server` headers, and the headers that
+    /// describe the body's framing: `Content-Length` when the body's length
+    /// is known and `Transfer-Encoding: chunked` otherwise.
+    pub fn write_to<W: Write>(mut self, writer: &mut W) -> io::Result<()> {
+        if !self.headers.contains("Date") {
+            self.headers.insert("Date", http_date(SystemTime::now()));
+        }
+        if !self.headers.contains("Server") {
+            self.headers.insert("Server", SERVER_NAME);
+        }
+
+        if self.status.allows_body() {
+            match self.body.len() {
+                Some(len) => self.headers.insert("Content-Length", len.to_string()),
+                None => self.headers.insert("Transfer-Encoding", "chunked"),
+            }
+        }
+
+        let head = format!("{HTTP_VERSION} {}\r\n{}\r\n", self.status, self.headers);
+        writer.write_all(head.as_bytes())?;
+
+        if self.status.allows_body() {
+            match self.body {
+                Body::Empty => {}
+                Body::Bytes(bytes) => writer.write_all(&bytes)?,
+                Body::File(file, len) => {
+                    io::copy(&mut file.take(len), writer)?;
+                }
+                Body::Reader(reader, len) => {
+                    io::copy(&mut reader.take(len), writer)?;
+                }
+                Body::Chunked(chunks) => {
+                    for chunk in chunks {
+                        let chunk = chunk?;
+                        if chunk.is_empty() {
+                            continue;
+                        }
+                        write!(writer, "{:X}\r\n", chunk.len())?;
+                        writer.write_all(&chunk)?;
+                        writer.write_all(b"\r\n")?;
+                    }
+                    writer.write_all(b"0\r\n\r\n")?;
+                }
+            }
+        }
+
+        writer.flush()
+    }
+
+    /// Write the response to `stream`, and then run the upgrade handler, if any
+    ///
+    /// The upgrade handler runs on the current thread, so a connection that is
+    /// handled by a `ThreadPool` worker stays on that worker after the upgrade.
+    pub fn send(mut self, mut stream: TcpStream) -> io::Result<()> {
+        let upgrade = self.upgrade.take();
+
+        self.write_to(&mut stream)?;
+
+        if let Some(upgrade) = upgrade {
+            upgrade.run(stream);
+        }
+
+        Ok(())
+    }
+}
+
+/// A builder for `Response`
+///
+/// Obtained from `Response::builder()`.
+#[derive(Debug)]
+pub struct ResponseBuilder {
+    response: Response,
+}
+
+impl ResponseBuilder {
+    /// Set the status
+    pub fn status(mut self, status: StatusCode) -> ResponseBuilder {
+        self.response.status = status;
+        self
+    }
+
+    /// Set a header, replacing any previous values with the same name
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> ResponseBuilder {
+        self.response.headers.insert(name, value);
+        self
+    }
+
+    /// Use `bytes` as the body
+    pub fn bytes(mut self, bytes: impl Into<Vec<u8>>) -> ResponseBuilder {
+        self.response.body = Body::Bytes(bytes.into());
+        self
+    }
+
+    /// Use `text` as the body
+    ///
+    /// Sets `Content-Type` to plain text if it hasn't been set.
+    pub fn text(mut self, text: impl Into<String>) -> ResponseBuilder {
+        if !self.response.headers.contains("Content-Type") {
+            self.response.headers.insert("Content-Type", "text/plain; charset=utf-8");
+        }
+        self.bytes(text.into())
+    }
+
+    /// Stream the file at `path` as the body
+    ///
+    /// Sets `Content-Type` from the file's extension if it hasn't been set.
+    pub fn file(mut self, path: impl AsRef<Path>) -> io::Result<ResponseBuilder> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        if !self.response.headers.contains("Content-Type") {
+            self.response.headers.insert("Content-Type", content_type(path));
+        }
+        self.response.body = Body::File(file, len);
+
+        Ok(self)
+    }
+
+    /// Stream `len` bytes from `reader` as the body
+    pub fn reader(mut self, reader: impl Read + Send + 'static, len: u64) -> ResponseBuilder {
+        self.response.body = Body::Reader(Box::new(reader), len);
+        self
+    }
+
+    /// Send the chunks produced by `chunks` as the body
+    ///
+    /// The body is sent with the chunked transfer encoding.
+    pub fn stream<I>(mut self, chunks: I) -> ResponseBuilder
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        self.response.body = Body::Chunked(Box::new(chunks.into_iter().map(Ok)));
+        self
+    }
+
+    /// Take over the connection with `handler` after the response has been sent
+    pub fn on_upgrade<F>(mut self, handler: F) -> ResponseBuilder
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        self.response.upgrade = Some(Upgrade(Box::new(handler)));
+        self
+    }
+
+    /// Finish building the response
+    pub fn build(self) -> Response {
+        self.response
+    }
+}
+
+/// Guess the `Content-Type` of a file from its extension
+pub fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Format a point in time as an HTTP date, for example, `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = secs / 86_400;
+    let (hour, minute, second) = (secs % 86_400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Convert days since the epoch to a civil date; see Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn written(response: Response) -> String {
+        let mut buffer = Vec::new();
+        response.write_to(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", http_date(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_write_bytes_with_default_headers() {
+        let response = Response::builder()
+            .status(StatusCode::NotFound)
+            .text("Oops!")
+            .build();
+        let text = written(response);
+
+        assert!(text.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(text.contains("\r\nContent-Length: 5\r\n"));
+        assert!(text.contains(&format!("\r\nServer: {SERVER_NAME}\r\n")));
+        assert!(text.contains("\r\nDate: "));
+        assert!(text.ends_with("\r\n\r\nOops!"));
+    }
+
+    #[test]
+    fn test_write_chunked_stream() {
+        let chunks = vec![b"Hello, ".to_vec(), Vec::new(), b"chunked world!".to_vec()];
+        let response = Response::builder().stream(chunks).build();
+        let text = written(response);
+
+        assert!(text.contains("\r\nTransfer-Encoding: chunked\r\n"));
+        assert!(!text.contains("Content-Length"));
+        assert!(text.ends_with("\r\n\r\n7\r\nHello, \r\nE\r\nchunked world!\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_write_cut_off_chunked_stream() {
+        let chunks = vec![Ok(b"Hello, ".to_vec()), Err(io::Error::from(io::ErrorKind::UnexpectedEof))];
+        let response = Response::builder().build();
+        let response = Response { body: Body::Chunked(Box::new(chunks.into_iter())), ..response };
+
+        let mut buffer = Vec::new();
+        assert!(response.write_to(&mut buffer).is_err());
+        assert!(buffer.ends_with(b"\r\n\r\n7\r\nHello, \r\n"), "Expected no last chunk after the error.");
+    }
+
+    #[test]
+    fn test_explicit_headers_are_kept() {
+        let response = Response::builder()
+            .header("Server", "custom")
+            .header("Content-Type", "text/html")
+            .text("<p>Hi</p>")
+            .build();
+
+        assert_eq!(Some("text/html"), response.headers.get("Content-Type"));
+        let text = written(response);
+        assert!(text.contains("\r\nServer: custom\r\n"));
+    }
+
+    #[test]
+    fn test_compressed_large_body() {
+        let response = Response::builder()
+            .text("Hi from Rust! ".repeat(200))
+            .build()
+            .compressed("gzip")
+            .unwrap();
+
+        assert_eq!(Encoding::Gzip, response.encoding());
+        assert_eq!(Some("Accept-Encoding"), response.headers.get("Vary"));
+    }
+}
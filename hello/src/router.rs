@@ -0,0 +1,327 @@
+//! Request routing
+//!
+//! A `Router` maps a request's method and path to a `Handler`.
+//! Path segments that start with a colon, as in `/users/:id`, match any
+//! single segment, which is captured into the request's `params`.
+//! Handlers can also be mounted under a path prefix, in which case
+//! they handle all methods and all paths below the prefix.
+//! Requests that don't match any route go to the fallback handler.
+//!
+//! Middleware registered with `Router::wrap()` runs around every handler,
+//! in registration order: the first registered middleware is the outermost.
+//! See the `middleware` module for details.
+
+use percent_encoding::percent_decode_str;
+
+use crate::middleware::Middleware;
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+
+/// Something that turns a request into a response
+///
+/// Implemented for all closures and functions with the right signature.
+pub trait Handler: Send + Sync {
+    fn handle(&self, request: &mut Request) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&mut Request) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &mut Request) -> Response {
+        self(request)
+    }
+}
+
+/// A single route: a method, a path, and the handler for them
+struct Route {
+    /// `None` matches any method
+    method: Option<String>,
+    path: String,
+    /// Whether the route also matches the paths below `path`
+    prefix: bool,
+    handler: Box<dyn Handler>,
+}
+
+impl Route {
+    /// Match `path` against the route's path
+    ///
+    /// Returns the captured parameters, percent-decoded, if it matches.
+    fn match_path(&self, path: &str) -> Option<Vec<(String, String)>> {
+        if self.prefix {
+            let matches =
+                path == self.path || path.strip_prefix(self.path.as_str()).is_some_and(|rest| rest.starts_with('/'));
+            return matches.then(Vec::new);
+        }
+
+        let mut params = Vec::new();
+        let mut pattern = self.path.split('/');
+        let mut segments = path.split('/');
+
+        loop {
+            match (pattern.next(), segments.next()) {
+                (None, None) => return Some(params),
+                (Some(expected), Some(segment)) => match expected.strip_prefix(':') {
+                    Some(name) if !segment.is_empty() => {
+                        let value = percent_decode_str(segment).decode_utf8_lossy().into_owned();
+                        params.push((name.to_string(), value));
+                    }
+                    _ if expected == segment => {}
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        self.match_path(path).is_some()
+    }
+
+    fn matches_method(&self, method: &str) -> bool {
+        self.method.as_deref().is_none_or(|m| m == method)
+    }
+}
+
+/// Dispatches requests to handlers, running them through middleware
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Box<dyn Handler>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Router {
+    /// Create a router without routes
+    ///
+    /// Until `fallback()` is called, unmatched requests get an empty `404 Not Found`.
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            fallback: Box::new(|_: &mut Request| Response::new(StatusCode::NotFound)),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Route requests with `method` and `path` to `handler`
+    ///
+    /// Routes are tried in the order in which they were added.
+    pub fn route(mut self, method: &str, path: &str, handler: impl Handler + 'static) -> Router {
+        self.routes.push(Route {
+            method: Some(method.to_string()),
+            path: path.to_string(),
+            prefix: false,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Route requests with any method for `prefix` and the paths below it to `handler`
+    ///
+    /// For example, a handler mounted at `/api` handles `/api` and `/api/pool`,
+    /// but not `/apiary`.
+    pub fn mount(mut self, prefix: &str, handler: impl Handler + 'static) -> Router {
+        self.routes.push(Route {
+            method: None,
+            path: prefix.trim_end_matches('/').to_string(),
+            prefix: true,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Route `GET` requests for `path` to `handler`
+    pub fn get(self, path: &str, handler: impl Handler + 'static) -> Router {
+        self.route("GET", path, handler)
+    }
+
+    /// Route `POST` requests for `path` to `handler`
+    pub fn post(self, path: &str, handler: impl Handler + 'static) -> Router {
+        self.route("POST", path, handler)
+    }
+
+    /// Handle requests that don't match any route with `handler`
+    pub fn fallback(mut self, handler: impl Handler + 'static) -> Router {
+        self.fallback = Box::new(handler);
+        self
+    }
+
+    /// Run every request through `middleware`
+    ///
+    /// Middleware runs in registration order.
+    pub fn wrap(mut self, middleware: impl Middleware + 'static) -> Router {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Serve a request
+    ///
+    /// The `before` hooks of the middleware run in registration order.
+    /// If one of them returns a response, the remaining `before` hooks and
+    /// the handler are skipped. Then the `after` hooks of the middleware whose
+    /// `before` hooks have run are called in reverse order.
+    pub fn handle(&self, request: &mut Request) -> Response {
+        let mut entered = 0;
+        let mut short_circuit = None;
+
+        for middleware in &self.middleware {
+            entered += 1;
+            if let Some(response) = middleware.before(request) {
+                short_circuit = Some(response);
+                break;
+            }
+        }
+
+        let mut response = match short_circuit {
+            Some(response) => response,
+            None => self.dispatch(request),
+        };
+
+        for middleware in self.middleware[..entered].iter().rev() {
+            middleware.after(request, &mut response);
+        }
+
+        response
+    }
+
+    /// Find the handler for a request and call it
+    ///
+    /// Answers `405 Method Not Allowed` if the path is routed,
+    /// but not for the request's method.
+    fn dispatch(&self, request: &mut Request) -> Response {
+        let path = request.path().to_string();
+
+        for route in self.routes.iter().filter(|r| r.matches_method(&request.method)) {
+            if let Some(params) = route.match_path(&path) {
+                request.params = params;
+                return route.handler.handle(request);
+            }
+        }
+
+        let allowed: Vec<&str> = self
+            .routes
+            .iter()
+            .filter(|r| r.matches_path(&path))
+            .filter_map(|r| r.method.as_deref())
+            .collect();
+
+        if allowed.is_empty() {
+            self.fallback.handle(request)
+        } else {
+            Response::builder()
+                .status(StatusCode::MethodNotAllowed)
+                .header("Allow", allowed.join(", "))
+                .build()
+        }
+    }
+}
+
+impl Handler for Router {
+    /// Lets a router serve as another router's handler, for example, for a virtual host
+    fn handle(&self, request: &mut Request) -> Response {
+        Router::handle(self, request)
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn request(method: &str, target: &str) -> Request {
+        Request::parse(format!("{method} {target} HTTP/1.1\r\n\r\n").as_bytes()).unwrap()
+    }
+
+    /// Records its name in a shared log on every hook, and optionally short-circuits
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+        short_circuit: bool,
+    }
+
+    impl Middleware for Recorder {
+        fn before(&self, _request: &mut Request) -> Option<Response> {
+            self.log.lock().unwrap().push(format!("before {}", self.name));
+            self.short_circuit.then(|| Response::new(StatusCode::Forbidden))
+        }
+
+        fn after(&self, _request: &Request, _response: &mut Response) {
+            self.log.lock().unwrap().push(format!("after {}", self.name));
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let router = Router::new()
+            .get("/", |_: &mut Request| Response::builder().text("root").build())
+            .post("/", |_: &mut Request| Response::new(StatusCode::NoContent))
+            .get("/sleep", |_: &mut Request| Response::builder().text("sleep").build());
+
+        assert_eq!(StatusCode::Ok, router.handle(&mut request("GET", "/?x=1")).status);
+        assert_eq!(StatusCode::NoContent, router.handle(&mut request("POST", "/")).status);
+        assert_eq!(StatusCode::NotFound, router.handle(&mut request("GET", "/foo")).status);
+
+        let response = router.handle(&mut request("DELETE", "/"));
+        assert_eq!(StatusCode::MethodNotAllowed, response.status);
+        assert_eq!(Some("GET, POST"), response.headers.get("Allow"));
+    }
+
+    #[test]
+    fn test_path_params() {
+        let router = Router::new().get("/users/:id/posts/:post", |request: &mut Request| {
+            let text = format!("{} {}", request.param("id").unwrap(), request.param("post").unwrap());
+            Response::builder().text(text).build()
+        });
+
+        let response = router.handle(&mut request("GET", "/users/42/posts/hello%20world"));
+        assert_eq!(StatusCode::Ok, response.status);
+        assert!(matches!(response.body, crate::response::Body::Bytes(ref body) if body == b"42 hello world"));
+
+        assert_eq!(StatusCode::NotFound, router.handle(&mut request("GET", "/users/42/posts")).status);
+        assert_eq!(StatusCode::NotFound, router.handle(&mut request("GET", "/users//posts/1")).status);
+    }
+
+    #[test]
+    fn test_mount() {
+        let router = Router::new().mount("/proxy/", |_: &mut Request| Response::new(StatusCode::Accepted));
+
+        assert_eq!(StatusCode::Accepted, router.handle(&mut request("GET", "/proxy")).status);
+        assert_eq!(StatusCode::Accepted, router.handle(&mut request("PUT", "/proxy/a/b?c=d")).status);
+        assert_eq!(StatusCode::NotFound, router.handle(&mut request("GET", "/proxyish")).status);
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name, short_circuit| Recorder { name, log: Arc::clone(&log), short_circuit };
+
+        let router = Router::new()
+            .get("/", |_: &mut Request| Response::new(StatusCode::Ok))
+            .wrap(recorder("outer", false))
+            .wrap(recorder("inner", false));
+
+        assert_eq!(StatusCode::Ok, router.handle(&mut request("GET", "/")).status);
+        assert_eq!(vec!["before outer", "before inner", "after inner", "after outer"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn test_middleware_short_circuit() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name, short_circuit| Recorder { name, log: Arc::clone(&log), short_circuit };
+
+        let router = Router::new()
+            .get("/", |_: &mut Request| panic!("The handler must not run."))
+            .wrap(recorder("outer", false))
+            .wrap(recorder("guard", true))
+            .wrap(recorder("inner", false));
+
+        assert_eq!(StatusCode::Forbidden, router.handle(&mut request("GET", "/")).status);
+        assert_eq!(vec!["before outer", "before guard", "after guard", "after outer"], *log.lock().unwrap());
+    }
+}
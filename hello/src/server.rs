@@ -0,0 +1,65 @@
+//! Serving connections
+//!
+//! Accepts connections from a listener and serves them on a `ThreadPool`,
+//! one request per connection.
+
+use std::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+use crate::router::Router;
+use crate::ThreadPool;
+
+/// Serve the connections that `listener` accepts with `router`
+///
+/// Each connection is handled by a worker from `pool`.
+/// Returns when the listener stops accepting connections.
+pub fn serve(listener: TcpListener, pool: &ThreadPool, router: Arc<Router>) {
+    // Practically an infinite loop, waiting for and serving client requests
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Couldn't accept a connection: {}", error);
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+
+        pool.execute(move || {
+            handle_connection(stream, &router);
+        });
+    }
+}
+
+/// Seems to be more stable than the original implementation, which can be found in `main.rs`.
+///
+/// The request is read through a buffered clone of the stream, and handlers
+/// read the request body, if they need it, from the same buffer.
+///
+/// An upgraded connection, like the sleep page's WebSocket, keeps running on
+/// this worker until its handler returns.
+pub fn handle_connection(stream: TcpStream, router: &Router) {
+    let reader = BufReader::new(stream.try_clone().expect("Expected to clone the TcpStream."));
+
+    let response = match Request::read_from(reader) {
+        Ok(Some(mut request)) => {
+            request.peer_addr = stream.peer_addr().ok();
+            router.handle(&mut request)
+        }
+        // The client closed the connection without sending a request.
+        Ok(None) => return,
+        Err(_) => Response::builder()
+            .status(StatusCode::BadRequest)
+            .text("Malformed request.")
+            .build(),
+    };
+
+    if let Err(error) = response.send(stream) {
+        eprintln!("Couldn't write the response: {}", error);
+    }
+}
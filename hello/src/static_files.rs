@@ -0,0 +1,127 @@
+//! Serving a directory of static files
+//!
+//! `StaticFiles` maps request paths to files under a document root.
+//! A path that names a directory serves the directory's `index.html`.
+//! Paths that would leave the document root, for example, through `..`,
+//! are never served. Missing files get the root's `404.html` if there is one.
+
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+use crate::router::Handler;
+
+/// The file that is served for a directory
+pub const INDEX_FILE: &str = "index.html";
+
+/// The file that is served, with `404 Not Found`, for missing files
+pub const NOT_FOUND_FILE: &str = "404.html";
+
+/// A handler that serves the files under a document root
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Map a request path to a file under the root
+    ///
+    /// Returns `None` if the path isn't valid UTF-8 once decoded,
+    /// or if it tries to leave the root.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+
+        let mut file = self.root.clone();
+        for component in Path::new(decoded.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(name) => file.push(name),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+
+        if file.is_dir() {
+            file.push(INDEX_FILE);
+        }
+
+        Some(file)
+    }
+
+    fn not_found(&self, accept_encoding: &str) -> Response {
+        Response::static_file(StatusCode::NotFound, &self.root.join(NOT_FOUND_FILE), accept_encoding)
+            .unwrap_or_else(|_| Response::builder().status(StatusCode::NotFound).text("Not found.").build())
+    }
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, request: &mut Request) -> Response {
+        if request.method != "GET" {
+            return Response::builder()
+                .status(StatusCode::MethodNotAllowed)
+                .header("Allow", "GET")
+                .build();
+        }
+
+        let accept_encoding = request.header("Accept-Encoding").unwrap_or_default().to_string();
+
+        match self.resolve(request.path()) {
+            Some(file) if file.is_file() => Response::static_file(StatusCode::Ok, &file, &accept_encoding)
+                .unwrap_or_else(|error| {
+                    eprintln!("Couldn't read '{}': {}", file.display(), error);
+                    Response::new(StatusCode::InternalServerError)
+                }),
+            _ => self.not_found(&accept_encoding),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::testing::TestResponse;
+
+    fn request(method: &str, target: &str) -> Request {
+        Request::parse(format!("{method} {target} HTTP/1.1\r\n\r\n").as_bytes()).unwrap()
+    }
+
+    fn body(response: Response) -> String {
+        TestResponse::from(response).text()
+    }
+
+    #[test]
+    fn test_serve_files() {
+        let root = env::temp_dir().join(format!("hello-static-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("index.html"), "home").unwrap();
+        fs::write(root.join("docs/a b.txt"), "spaced").unwrap();
+        fs::write(root.join("404.html"), "missing").unwrap();
+        let files = StaticFiles::new(&root);
+
+        let response = files.handle(&mut request("GET", "/"));
+        assert_eq!(StatusCode::Ok, response.status);
+        assert_eq!("home", body(response));
+
+        let response = files.handle(&mut request("GET", "/docs/a%20b.txt?x=1"));
+        assert_eq!(StatusCode::Ok, response.status);
+        assert_eq!(Some("text/plain; charset=utf-8"), response.headers.get("Content-Type"));
+        assert_eq!("spaced", body(response));
+
+        for target in ["/nope", "/docs", "/../index.html", "/docs/%2e%2e/%2e%2e/etc/passwd"] {
+            let response = files.handle(&mut request("GET", target));
+            assert_eq!(StatusCode::NotFound, response.status, "{target}");
+            assert_eq!("missing", body(response), "{target}");
+        }
+
+        assert_eq!(StatusCode::MethodNotAllowed, files.handle(&mut request("POST", "/")).status);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+//! A harness for testing routers
+//!
+//! A `TestClient` sends requests and returns `TestResponse`s, whose status,
+//! headers and body can be asserted on. It can talk to a router in-process,
+//! without a socket, or to a `TestServer` that serves a router on an
+//! ephemeral port. The latter is needed for upgraded connections,
+//! for example, WebSockets, which `TestServer::websocket()` opens.
+//!
+//! In both cases, responses go through the same serialization as in production,
+//! so the headers that are added when a response is written can be checked too.
+//!
+//! The module is only compiled with the `testing` feature, so that it isn't part
+//! of the server. The integration tests turn it on through a dev-dependency.
+//!
+//! ```
+//! use hello::response::{Response, StatusCode};
+//! use hello::request::Request;
+//! use hello::router::Router;
+//! use hello::testing::TestClient;
+//!
+//! let router = Router::new().get("/", |_: &mut Request| Response::builder().text("Hi!").build());
+//! let client = TestClient::new(router);
+//!
+//! let response = client.get("/");
+//! assert_eq!(StatusCode::Ok, response.status);
+//! assert_eq!("Hi!", response.text());
+//! ```
+
+use std::{
+    io::{self, BufReader, Cursor, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::chunked::ChunkedReader;
+use crate::headers::Headers;
+use crate::request::{self, Request};
+use crate::response::{Response, StatusCode};
+use crate::router::Router;
+use crate::server;
+use crate::websocket::{self, Frame, Opcode};
+use crate::ThreadPool;
+
+/// The number of workers that serve a `TestServer`
+pub const TEST_SERVER_THREADS: usize = 4;
+
+/// How long a client waits for the server before giving up
+pub const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The key that test WebSocket handshakes use; it's the one from RFC 6455
+const WEBSOCKET_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+
+/// Where a `TestClient` sends its requests
+#[derive(Clone)]
+enum Target {
+    Router(Arc<Router>),
+    Socket(SocketAddr),
+}
+
+/// Sends requests to a router, either in-process or over a socket
+#[derive(Clone)]
+pub struct TestClient {
+    target: Target,
+}
+
+impl TestClient {
+    /// Create a client that calls `router` directly, without a socket
+    ///
+    /// Requests appear to come from `127.0.0.1`. Upgrade handlers aren't run.
+    pub fn new(router: Router) -> TestClient {
+        TestClient { target: Target::Router(Arc::new(router)) }
+    }
+
+    /// Start building a request
+    pub fn request(&self, method: &str, target: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Send a `GET` request for `target`
+    pub fn get(&self, target: &str) -> TestResponse {
+        self.request("GET", target).send()
+    }
+
+    /// Send a `POST` request for `target` with `body`
+    pub fn post(&self, target: &str, content_type: &str, body: impl Into<Vec<u8>>) -> TestResponse {
+        self.request("POST", target).header("Content-Type", content_type).body(body).send()
+    }
+}
+
+/// A request that is being built by a `TestClient`
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    method: String,
+    target: String,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl TestRequest<'_> {
+    /// Set a header, replacing any previous values with the same name
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Use `body` as the body
+    ///
+    /// `Content-Length` is set when the request is sent.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request and wait for the response
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server can't be reached or sends a malformed response.
+    pub fn send(self) -> TestResponse {
+        let raw = self.to_bytes();
+
+        let bytes = match &self.client.target {
+            Target::Router(router) => {
+                let mut request = Request::parse(&raw).expect("Expected a well-formed test request.");
+                request.peer_addr = Some(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+
+                let mut bytes = Vec::new();
+                router.handle(&mut request).write_to(&mut bytes).expect("Expected to write the response.");
+                bytes
+            }
+            Target::Socket(addr) => {
+                let mut stream = connect(*addr).expect("Expected to connect to the test server.");
+                stream.write_all(&raw).expect("Expected to send the request.");
+
+                let mut bytes = Vec::new();
+                stream.read_to_end(&mut bytes).expect("Expected to read the response.");
+                bytes
+            }
+        };
+
+        TestResponse::parse(&bytes).expect("Expected a well-formed response.")
+    }
+
+    /// Serialize the request, with the headers that every request needs
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut headers = self.headers.clone();
+        if !headers.contains("Host") {
+            headers.insert("Host", "localhost");
+        }
+        if !self.body.is_empty() && !headers.contains("Content-Length") {
+            headers.insert("Content-Length", self.body.len().to_string());
+        }
+        headers.insert("Connection", "close");
+
+        let mut raw = format!("{} {} HTTP/1.1\r\n", self.method, self.target).into_bytes();
+        for (name, value) in headers.iter() {
+            raw.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        raw.extend_from_slice(b"\r\n");
+        raw.extend_from_slice(&self.body);
+        raw
+    }
+}
+
+/// A response received by a `TestClient`
+///
+/// A chunked body has been decoded, but content codings, like gzip, have not.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// Parse a response from the bytes that a server sent
+    pub fn parse(bytes: &[u8]) -> io::Result<TestResponse> {
+        let mut reader = Cursor::new(bytes);
+        let (status_line, headers) = request::read_head(&mut reader)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Empty response."))?;
+
+        let code = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed status line."))?;
+
+        let mut body = Vec::new();
+        if request::is_chunked(&headers) {
+            ChunkedReader::new(reader).read_to_end(&mut body)?;
+        } else {
+            reader.read_to_end(&mut body)?;
+        }
+
+        Ok(TestResponse { status: StatusCode::from_code(code), headers, body })
+    }
+
+    /// Return the value of the first header named `name`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// The body as text; invalid UTF-8 is replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+impl From<Response> for TestResponse {
+    /// Serialize `response` and read it back, as a client would see it
+    fn from(response: Response) -> TestResponse {
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).expect("Expected to write the response.");
+        TestResponse::parse(&bytes).expect("Expected a well-formed response.")
+    }
+}
+
+/// Serves a router on an ephemeral port of the loopback interface
+///
+/// Connections are handled on a `ThreadPool`, as in production.
+/// The server runs until the test process exits.
+pub struct TestServer {
+    addr: SocketAddr,
+}
+
+impl TestServer {
+    /// Start serving `router`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no port is available.
+    pub fn start(router: Router) -> TestServer {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Expected to bind an ephemeral port.");
+        let addr = listener.local_addr().expect("Expected the listener's address.");
+        let router = Arc::new(router);
+
+        thread::spawn(move || {
+            let pool = ThreadPool::new(TEST_SERVER_THREADS);
+            server::serve(listener, &pool, router);
+        });
+
+        TestServer { addr }
+    }
+
+    /// The address that the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Create a client that sends requests to the server
+    pub fn client(&self) -> TestClient {
+        TestClient { target: Target::Socket(self.addr) }
+    }
+
+    /// Open a WebSocket to `path`
+    ///
+    /// Fails if the server doesn't complete the handshake.
+    pub fn websocket(&self, path: &str) -> io::Result<TestWebSocket> {
+        let mut stream = connect(self.addr)?;
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {WEBSOCKET_KEY}\r\nSec-WebSocket-Version: {}\r\n\r\n",
+            websocket::WEBSOCKET_VERSION
+        )?;
+
+        let mut reader = BufReader::new(stream);
+        let (status_line, headers) = request::read_head(&mut reader)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Empty handshake response."))?;
+
+        let accepted = status_line.split_whitespace().nth(1) == Some("101")
+            && headers.get("Sec-WebSocket-Accept") == Some(websocket::accept_key(WEBSOCKET_KEY).as_str());
+        if !accepted {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Handshake refused: {status_line}")));
+        }
+
+        Ok(TestWebSocket { reader })
+    }
+}
+
+/// The client side of a WebSocket opened by `TestServer::websocket()`
+pub struct TestWebSocket {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestWebSocket {
+    /// Read the next frame that the server sent
+    ///
+    /// Server frames are never masked, so `Frame::read_from()` can't read them.
+    pub fn read_frame(&mut self) -> io::Result<Frame> {
+        let mut head = [0; 2];
+        self.reader.read_exact(&mut head)?;
+
+        let opcode = Opcode::from_bits(head[0] & 0x0F)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown opcode."))?;
+
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                self.reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+
+        let mut payload = Vec::new();
+        self.reader.by_ref().take(len).read_to_end(&mut payload)?;
+
+        Ok(Frame { fin: head[0] & 0x80 != 0, opcode, payload })
+    }
+
+    /// Read the next text message
+    ///
+    /// Returns `Ok(None)` once the server closes the connection.
+    pub fn read_text(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                Opcode::Text => {
+                    let text = String::from_utf8(frame.payload)
+                        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                    return Ok(Some(text));
+                }
+                Opcode::Close => return Ok(None),
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// Connect to `addr`, with timeouts so that a broken server fails the test instead of hanging it
+fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&addr, TEST_TIMEOUT)?;
+    stream.set_read_timeout(Some(TEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(TEST_TIMEOUT))?;
+    Ok(stream)
+}
@@ -0,0 +1,120 @@
+//! Virtual hosts
+//!
+//! `VirtualHosts` serves several sites from one server. It picks the site
+//! by the request's `Host` header, and hands requests for unknown hosts,
+//! or without a `Host` header, to the default site.
+//!
+//! Host names are compared case-insensitively and without the port.
+//! A pattern like `*.example.com` matches any subdomain of `example.com`,
+//! but not `example.com` itself. Exact names take precedence over patterns.
+
+use std::sync::Arc;
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::router::Handler;
+
+/// A handler that dispatches requests to sites by their `Host` header
+///
+/// Sites are shared, so that the same site can serve as the default, too.
+pub struct VirtualHosts {
+    sites: Vec<(String, Arc<dyn Handler>)>,
+    default: Arc<dyn Handler>,
+}
+
+impl VirtualHosts {
+    /// Create virtual hosts that send all requests to `default`, until sites are added
+    pub fn new(default: Arc<dyn Handler>) -> VirtualHosts {
+        VirtualHosts { sites: Vec::new(), default }
+    }
+
+    /// Serve `site` for each of `hosts`
+    pub fn site(mut self, hosts: &[&str], site: Arc<dyn Handler>) -> VirtualHosts {
+        for host in hosts {
+            self.sites.push((host.to_ascii_lowercase(), Arc::clone(&site)));
+        }
+        self
+    }
+
+    /// Find the site for `host`, which must already be normalized with `host_name()`
+    fn find(&self, host: &str) -> Option<&dyn Handler> {
+        let exact = self.sites.iter().find(|(name, _)| name == host);
+        let wildcard = || {
+            self.sites.iter().find(|(name, _)| {
+                name.strip_prefix("*.")
+                    .is_some_and(|domain| host.strip_suffix(domain).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')))
+            })
+        };
+
+        exact.or_else(wildcard).map(|(_, site)| site.as_ref())
+    }
+}
+
+impl Handler for VirtualHosts {
+    fn handle(&self, request: &mut Request) -> Response {
+        match host_name(request).and_then(|host| self.find(&host)) {
+            Some(site) => site.handle(request),
+            None => self.default.handle(request),
+        }
+    }
+}
+
+/// The host that a request is for, from its `Host` header
+///
+/// Lowercased, without the port and without a trailing dot.
+/// IPv6 addresses keep their brackets, as in `[::1]`.
+pub fn host_name(request: &Request) -> Option<String> {
+    let host = request.header("Host")?.trim();
+
+    let name = if host.starts_with('[') {
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.rsplit_once(':').map_or(host, |(name, _)| name)
+    };
+
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::StatusCode;
+
+    fn request(host: Option<&str>) -> Request {
+        let host = host.map(|host| format!("Host: {host}\r\n")).unwrap_or_default();
+        Request::parse(format!("GET / HTTP/1.1\r\n{host}\r\n").as_bytes()).unwrap()
+    }
+
+    fn site(status: StatusCode) -> Arc<dyn Handler> {
+        Arc::new(move |_: &mut Request| Response::new(status))
+    }
+
+    #[test]
+    fn test_host_name() {
+        assert_eq!(Some("example.com".to_string()), host_name(&request(Some("Example.COM:8080"))));
+        assert_eq!(Some("example.com".to_string()), host_name(&request(Some("example.com."))));
+        assert_eq!(Some("[::1]".to_string()), host_name(&request(Some("[::1]:7878"))));
+        assert_eq!(None, host_name(&request(Some(""))));
+        assert_eq!(None, host_name(&request(None)));
+    }
+
+    #[test]
+    fn test_dispatch_by_host() {
+        let hosts = VirtualHosts::new(site(StatusCode::NotFound))
+            .site(&["a.test", "www.a.test"], site(StatusCode::Ok))
+            .site(&["*.b.test"], site(StatusCode::Accepted))
+            .site(&["special.b.test"], site(StatusCode::Created));
+
+        let status = |host| hosts.handle(&mut request(host)).status;
+        assert_eq!(StatusCode::Ok, status(Some("a.test")));
+        assert_eq!(StatusCode::Ok, status(Some("WWW.A.TEST:7878")));
+        assert_eq!(StatusCode::Accepted, status(Some("x.b.test")));
+        assert_eq!(StatusCode::Accepted, status(Some("x.y.b.test")));
+        assert_eq!(StatusCode::Created, status(Some("special.b.test")));
+        assert_eq!(StatusCode::NotFound, status(Some("b.test")));
+        assert_eq!(StatusCode::NotFound, status(Some("xb.test")));
+        assert_eq!(StatusCode::NotFound, status(Some("c.test")));
+        assert_eq!(StatusCode::NotFound, status(None));
+    }
+}
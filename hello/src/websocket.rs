@@ -0,0 +1,543 @@
+//! WebSocket connections, as specified in RFC 6455
+//!
+//! A client opens a WebSocket by sending an HTTP upgrade request.
+//! `upgrade()` validates it and answers with `101 Switching Protocols`.
+//! After that, the connection is handed over to a callback, which talks
+//! to the client through a `WebSocket` that reads and writes frames.
+//!
+//! Pings are answered with pongs, fragmented messages are reassembled,
+//! and the closing handshake is completed automatically.
+
+use std::{
+    fmt::{Display, Formatter},
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha1::{Digest, Sha1};
+
+use crate::request::Request;
+use crate::response::{Response, StatusCode};
+
+/// The GUID that is appended to the client's key in the opening handshake
+pub const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only protocol version that we support
+pub const WEBSOCKET_VERSION: &str = "13";
+
+/// The largest message payload that we accept from a client, in bytes
+pub const MAX_PAYLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How long to wait for the client's reply to our close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status codes used in close frames
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// Compute the `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Check whether `request` is a valid WebSocket opening handshake
+pub fn is_upgrade_request(request: &Request) -> bool {
+    let has_token = |name: &str, token: &str| {
+        request
+            .headers
+            .get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+
+    request.method == "GET"
+        && has_token("Upgrade", "websocket")
+        && has_token("Connection", "upgrade")
+        && request.header("Sec-WebSocket-Version") == Some(WEBSOCKET_VERSION)
+        && request.header("Sec-WebSocket-Key").is_some()
+}
+
+/// Answer a WebSocket opening handshake
+///
+/// Returns `101 Switching Protocols` with `on_open` as the upgrade handler,
+/// or `400 Bad Request` if `request` isn't a valid handshake.
+pub fn upgrade<F>(request: &Request, on_open: F) -> Response
+where
+    F: FnOnce(WebSocket<TcpStream>) + Send + 'static,
+{
+    if !is_upgrade_request(request) {
+        return Response::builder()
+            .status(StatusCode::BadRequest)
+            .header("Sec-WebSocket-Version", WEBSOCKET_VERSION)
+            .text("Expected a WebSocket upgrade request.")
+            .build();
+    }
+
+    let key = request.header("Sec-WebSocket-Key").unwrap_or_default();
+
+    Response::builder()
+        .status(StatusCode::SwitchingProtocols)
+        .header("Upgrade", "websocket")
+        .header("Connection", "Upgrade")
+        .header("Sec-WebSocket-Accept", accept_key(key))
+        .on_upgrade(move |stream| on_open(WebSocket::new(stream)))
+        .build()
+}
+
+/// The type of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    pub(crate) fn from_bits(bits: u8) -> Option<Opcode> {
+        match bits {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xA,
+        }
+    }
+
+    fn is_control(&self) -> bool {
+        matches!(self, Opcode::Close | Opcode::Ping | Opcode::Pong)
+    }
+}
+
+/// A single WebSocket frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Create a final (unfragmented) frame
+    pub fn new(opcode: Opcode, payload: impl Into<Vec<u8>>) -> Frame {
+        Frame { fin: true, opcode, payload: payload.into() }
+    }
+
+    /// Read a frame from `reader`, unmasking its payload
+    ///
+    /// Frames sent by clients must be masked.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Frame, WebSocketError> {
+        let mut head = [0; 2];
+        reader.read_exact(&mut head)?;
+
+        let fin = head[0] & 0x80 != 0;
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("Reserved bits must be zero."));
+        }
+        let opcode = Opcode::from_bits(head[0] & 0x0F).ok_or(WebSocketError::Protocol("Unknown opcode."))?;
+
+        let masked = head[1] & 0x80 != 0;
+        if !masked {
+            return Err(WebSocketError::Protocol("Client frames must be masked."));
+        }
+
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+
+        if opcode.is_control() && (len > 125 || !fin) {
+            return Err(WebSocketError::Protocol("Control frames must be short and unfragmented."));
+        }
+        if len > MAX_PAYLOAD_SIZE {
+            return Err(WebSocketError::TooBig);
+        }
+
+        let mut mask = [0; 4];
+        reader.read_exact(&mut mask)?;
+
+        let mut payload = vec![0; len as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Frame { fin, opcode, payload })
+    }
+
+    /// Write the frame to `writer`
+    ///
+    /// Frames sent by servers are never masked.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = Vec::with_capacity(10);
+        head.push(if self.fin { 0x80 } else { 0x00 } | self.opcode.bits());
+
+        let len = self.payload.len();
+        if len < 126 {
+            head.push(len as u8);
+        } else if len <= u16::MAX as usize {
+            head.push(126);
+            head.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            head.push(127);
+            head.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        writer.write_all(&head)?;
+        writer.write_all(&self.payload)?;
+        writer.flush()
+    }
+}
+
+/// A complete message received from the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The client closed the connection, with an optional status code and reason
+    Close(Option<u16>, String),
+}
+
+/// An open WebSocket connection
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    closed: bool,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Wrap a stream on which the opening handshake has already been completed
+    pub fn new(stream: S) -> WebSocket<S> {
+        WebSocket { stream, closed: false }
+    }
+
+    /// Send a text message
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        Frame::new(Opcode::Text, text).write_to(&mut self.stream)
+    }
+
+    /// Send a binary message
+    pub fn send_binary(&mut self, bytes: &[u8]) -> io::Result<()> {
+        Frame::new(Opcode::Binary, bytes).write_to(&mut self.stream)
+    }
+
+    /// Send a ping; the client answers with a `Message::Pong`
+    pub fn ping(&mut self, payload: &[u8]) -> io::Result<()> {
+        Frame::new(Opcode::Ping, payload).write_to(&mut self.stream)
+    }
+
+    /// Check whether the connection has been closed
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Read the next message
+    ///
+    /// Answers pings and reassembles fragmented messages.
+    /// When the client closes the connection, the close frame is echoed
+    /// and `Message::Close` is returned. Protocol violations close the
+    /// connection with the appropriate status code.
+    pub fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        let mut fragments: Option<(Opcode, Vec<u8>)> = None;
+
+        loop {
+            let frame = match Frame::read_from(&mut self.stream) {
+                Ok(frame) => frame,
+                Err(error) => {
+                    if let Some(code) = error.close_code() {
+                        let _ = self.send_close(code, &error.to_string());
+                        self.closed = true;
+                    }
+                    return Err(error);
+                }
+            };
+
+            match frame.opcode {
+                Opcode::Ping => Frame::new(Opcode::Pong, frame.payload).write_to(&mut self.stream)?,
+                Opcode::Pong => return Ok(Message::Pong(frame.payload)),
+                Opcode::Close => {
+                    let (code, reason) = parse_close_payload(&frame.payload);
+                    if !self.closed {
+                        self.send_close(code.unwrap_or(CLOSE_NORMAL), "")?;
+                        self.closed = true;
+                    }
+                    return Ok(Message::Close(code, reason));
+                }
+                Opcode::Text | Opcode::Binary if fragments.is_none() => {
+                    if frame.fin {
+                        return self.complete(frame.opcode, frame.payload);
+                    }
+                    fragments = Some((frame.opcode, frame.payload));
+                }
+                Opcode::Continuation if fragments.is_some() => {
+                    let (opcode, mut payload) = fragments.take().unwrap_or((Opcode::Binary, Vec::new()));
+                    payload.extend_from_slice(&frame.payload);
+                    if payload.len() as u64 > MAX_PAYLOAD_SIZE {
+                        let _ = self.send_close(CLOSE_TOO_BIG, "");
+                        self.closed = true;
+                        return Err(WebSocketError::TooBig);
+                    }
+                    if frame.fin {
+                        return self.complete(opcode, payload);
+                    }
+                    fragments = Some((opcode, payload));
+                }
+                _ => {
+                    let _ = self.send_close(CLOSE_PROTOCOL_ERROR, "");
+                    self.closed = true;
+                    return Err(WebSocketError::Protocol("Unexpected frame in fragmented message."));
+                }
+            }
+        }
+    }
+
+    /// Close the connection and wait for the client to confirm it
+    ///
+    /// Messages that arrive before the client's close frame are discarded.
+    pub fn close(mut self, code: u16, reason: &str) -> io::Result<S>
+    where
+        S: AsTimeout,
+    {
+        if !self.closed {
+            self.send_close(code, reason)?;
+            self.closed = true;
+        }
+
+        self.stream.set_timeout(Some(CLOSE_TIMEOUT))?;
+        loop {
+            match Frame::read_from(&mut self.stream) {
+                Ok(frame) if frame.opcode == Opcode::Close => break,
+                Ok(_) => continue,
+                Err(_) => break,
+            }
+        }
+
+        Ok(self.stream)
+    }
+
+    fn send_close(&mut self, code: u16, reason: &str) -> io::Result<()> {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        payload.truncate(125);
+
+        Frame::new(Opcode::Close, payload).write_to(&mut self.stream)
+    }
+
+    fn complete(&mut self, opcode: Opcode, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+        if opcode == Opcode::Binary {
+            return Ok(Message::Binary(payload));
+        }
+
+        match String::from_utf8(payload) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => {
+                let _ = self.send_close(CLOSE_INVALID_DATA, "");
+                self.closed = true;
+                Err(WebSocketError::InvalidUtf8)
+            }
+        }
+    }
+}
+
+/// Streams whose reads can time out
+///
+/// Needed to bound the wait for the closing handshake.
+pub trait AsTimeout {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl AsTimeout for TcpStream {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)
+    }
+}
+
+/// Split a close frame's payload into the status code and the reason
+fn parse_close_payload(payload: &[u8]) -> (Option<u16>, String) {
+    if payload.len() < 2 {
+        return (None, String::new());
+    }
+
+    let code = u16::from_be_bytes([payload[0], payload[1]]);
+    let reason = String::from_utf8_lossy(&payload[2..]).into_owned();
+
+    (Some(code), reason)
+}
+
+/// An error that occurred while reading from a WebSocket
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    Protocol(&'static str),
+    TooBig,
+    InvalidUtf8,
+}
+
+impl WebSocketError {
+    /// The status code to close the connection with, if the error calls for it
+    fn close_code(&self) -> Option<u16> {
+        match self {
+            WebSocketError::Io(_) => None,
+            WebSocketError::Protocol(_) => Some(CLOSE_PROTOCOL_ERROR),
+            WebSocketError::TooBig => Some(CLOSE_TOO_BIG),
+            WebSocketError::InvalidUtf8 => Some(CLOSE_INVALID_DATA),
+        }
+    }
+}
+
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebSocketError::Io(error) => write!(f, "WebSocket I/O error: {error}"),
+            WebSocketError::Protocol(reason) => write!(f, "WebSocket protocol error: {reason}"),
+            WebSocketError::TooBig => write!(f, "WebSocket message is larger than {MAX_PAYLOAD_SIZE} bytes."),
+            WebSocketError::InvalidUtf8 => write!(f, "WebSocket text message is not valid UTF-8."),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl From<io::Error> for WebSocketError {
+    fn from(error: io::Error) -> Self {
+        WebSocketError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// An in-memory stream that reads from `input` and writes to `output`
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Encode a frame the way a client does, with a mask
+    fn client_frame(fin: bool, opcode: Opcode, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut frame = vec![if fin { 0x80 } else { 0x00 } | opcode.bits(), 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        frame
+    }
+
+    fn socket(input: Vec<u8>) -> WebSocket<MockStream> {
+        WebSocket::new(MockStream { input: Cursor::new(input), output: Vec::new() })
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // The example from RFC 6455, section 1.3
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+    }
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let raw = b"GET /sleep/ws HTTP/1.1\r\nHost: 127.0.0.1:7878\r\nUpgrade: websocket\r\n\
+Connection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        let request = Request::parse(raw).unwrap();
+        assert!(is_upgrade_request(&request));
+
+        let plain = Request::parse(b"GET /sleep/ws HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!is_upgrade_request(&plain));
+        assert_eq!(StatusCode::BadRequest, upgrade(&plain, |_| {}).status);
+    }
+
+    #[test]
+    fn test_read_masked_text_frame() {
+        let mut ws = socket(client_frame(true, Opcode::Text, b"Hello"));
+        assert_eq!(Message::Text(String::from("Hello")), ws.read_message().unwrap());
+    }
+
+    #[test]
+    fn test_fragmented_message_with_interleaved_ping() {
+        let mut input = client_frame(false, Opcode::Text, b"Hel");
+        input.extend(client_frame(true, Opcode::Ping, b"?"));
+        input.extend(client_frame(true, Opcode::Continuation, b"lo"));
+        let mut ws = socket(input);
+
+        assert_eq!(Message::Text(String::from("Hello")), ws.read_message().unwrap());
+        assert_eq!(vec![0x8A, 0x01, b'?'], ws.stream.output);
+    }
+
+    #[test]
+    fn test_close_is_echoed() {
+        let mut payload = CLOSE_GOING_AWAY.to_be_bytes().to_vec();
+        payload.extend_from_slice(b"bye");
+        let mut ws = socket(client_frame(true, Opcode::Close, &payload));
+
+        assert_eq!(Message::Close(Some(CLOSE_GOING_AWAY), String::from("bye")), ws.read_message().unwrap());
+        assert!(ws.is_closed());
+        assert_eq!(vec![0x88, 0x02, 0x03, 0xE9], ws.stream.output);
+    }
+
+    #[test]
+    fn test_unmasked_frame_is_a_protocol_error() {
+        let mut ws = socket(vec![0x81, 0x02, b'H', b'i']);
+
+        assert!(matches!(ws.read_message(), Err(WebSocketError::Protocol(_))));
+        assert!(ws.is_closed());
+    }
+
+    #[test]
+    fn test_write_extended_length() {
+        let mut output = Vec::new();
+        Frame::new(Opcode::Binary, vec![0; 300]).write_to(&mut output).unwrap();
+
+        assert_eq!(&[0x82, 126, 0x01, 0x2C], &output[..4]);
+        assert_eq!(304, output.len());
+    }
+}
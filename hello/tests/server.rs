@@ -0,0 +1,142 @@
+//! Integration tests for the pages of our web server
+//!
+//! Most tests call the router in-process. The sleep page's countdown needs
+//! a real connection for its WebSocket, so it's tested against a `TestServer`.
+
+use std::{env, fs, sync::Arc};
+
+use hello::{
+    app,
+    config::ServerConfig,
+    constants::*,
+    middleware::REQUEST_ID_HEADER,
+    response::StatusCode,
+    router::Router,
+    testing::{TestClient, TestServer},
+    PoolStats,
+};
+
+fn router() -> Router {
+    app::router(&ServerConfig::default(), Arc::new(PoolStats::default()))
+}
+
+fn client() -> TestClient {
+    TestClient::new(router())
+}
+
+fn template(path: &str) -> String {
+    fs::read_to_string(path).expect("Expected to read the template.")
+}
+
+#[test]
+fn test_root_serves_the_hello_page() {
+    let response = client().get(ROOT_PATH);
+
+    assert_eq!(StatusCode::Ok, response.status);
+    assert_eq!(Some("text/html; charset=utf-8"), response.header("Content-Type"));
+    assert_eq!(template(HELLO_HTML), response.text());
+    assert!(response.header(REQUEST_ID_HEADER).is_some());
+    assert!(response.header("Date").is_some());
+}
+
+#[test]
+fn test_root_is_too_small_to_compress() {
+    let response = client().request("GET", ROOT_PATH).header("Accept-Encoding", "gzip").send();
+
+    assert_eq!(StatusCode::Ok, response.status);
+    assert_eq!(None, response.header("Content-Encoding"));
+    assert_eq!(Some("Accept-Encoding"), response.header("Vary"));
+    assert_eq!(template(HELLO_HTML), response.text());
+}
+
+#[test]
+fn test_root_rejects_other_methods() {
+    let response = client().post(ROOT_PATH, "text/plain", "hi");
+
+    assert_eq!(StatusCode::MethodNotAllowed, response.status);
+    assert_eq!(Some("GET"), response.header("Allow"));
+}
+
+#[test]
+fn test_sleep_serves_the_counter_page() {
+    let response = client().get(SLEEP_PATH);
+
+    assert_eq!(StatusCode::Ok, response.status);
+    assert_eq!(template(SLEEP_COUNTER_HTML), response.text());
+    assert!(response.text().contains(SLEEP_WS_PATH));
+}
+
+#[test]
+fn test_sleep_counts_down_over_a_websocket() {
+    let server = TestServer::start(router());
+    let mut socket = server.websocket(SLEEP_WS_PATH).expect("Expected to open the WebSocket.");
+
+    let mut messages = Vec::new();
+    while let Some(text) = socket.read_text().expect("Expected to read a message.") {
+        messages.push(text);
+    }
+
+    let mut expected: Vec<String> = (1..=SLEEP_SECS).rev().map(|secs| secs.to_string()).collect();
+    expected.push(SLEEP_READY.to_string());
+    assert_eq!(expected, messages);
+}
+
+#[test]
+fn test_sleep_websocket_requires_an_upgrade() {
+    assert_eq!(StatusCode::BadRequest, client().get(SLEEP_WS_PATH).status);
+}
+
+#[test]
+fn test_unknown_paths_get_the_404_page() {
+    for path in ["/foo", "/sleep/", "/hello.html?x=1"] {
+        let response = client().get(path);
+
+        assert_eq!(StatusCode::NotFound, response.status, "{path}");
+        assert_eq!(template(NOT_FOUND_404_HTML), response.text(), "{path}");
+    }
+}
+
+#[test]
+fn test_pages_are_served_over_a_socket() {
+    let server = TestServer::start(router());
+    let client = server.client();
+
+    assert_eq!(template(HELLO_HTML), client.get(ROOT_PATH).text());
+    assert_eq!(StatusCode::NotFound, client.get("/foo").status);
+}
+
+#[test]
+fn test_virtual_hosts() {
+    let root = env::temp_dir().join(format!("hello-vhosts-{}", std::process::id()));
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::create_dir_all(root.join("fallback")).unwrap();
+    fs::write(root.join("docs/index.html"), "docs home").unwrap();
+    fs::write(root.join("fallback/index.html"), "fallback home").unwrap();
+
+    let config = ServerConfig::parse(&format!(
+        r#"{{
+            "sites": [
+                {{ "hosts": ["localhost"] }},
+                {{ "hosts": ["docs.test", "*.docs.test"], "root": {:?} }},
+                {{ "default": true, "root": {:?} }}
+            ]
+        }}"#,
+        root.join("docs"),
+        root.join("fallback"),
+    ))
+    .unwrap();
+    let client = TestClient::new(app::router(&config, Arc::new(PoolStats::default())));
+    let get = |host: &str, path: &str| client.request("GET", path).header("Host", host).send();
+
+    assert_eq!(template(HELLO_HTML), get("localhost:7878", ROOT_PATH).text());
+    assert_eq!(StatusCode::Ok, get("LOCALHOST", SLEEP_PATH).status);
+    assert_eq!("docs home", get("docs.test", ROOT_PATH).text());
+    assert_eq!("docs home", get("v2.docs.test", ROOT_PATH).text());
+    assert_eq!(StatusCode::NotFound, get("docs.test", SLEEP_PATH).status);
+    assert_eq!("fallback home", get("unknown.test", ROOT_PATH).text());
+
+    // The middleware runs for every site.
+    assert!(get("docs.test", ROOT_PATH).header(REQUEST_ID_HEADER).is_some());
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,125 @@
+use std::fmt::{self, Display, Formatter, Write};
+use std::ops::Range;
+
+
+/// The environment variable that sets the colors of the output
+pub const COLORS_ENV: &str = "MINIGREP_COLORS";
+
+/// Ends a colored span in the output
+const RESET: &str = "\x1b[0m";
+
+/// The colors of the parts of the output, as ANSI SGR parameters like `1;31`
+///
+/// An empty parameter leaves its part uncolored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Matches in selected lines
+    pub matched: String,
+    pub path: String,
+    pub line_number: String,
+    /// The `:` and `-` after file names and line numbers, and the `--` between groups of lines
+    pub separator: String,
+}
+
+impl Default for Theme {
+    /// The colors of GNU grep: bold red matches, magenta paths, green line numbers and cyan separators
+    fn default() -> Theme {
+        Theme {
+            matched: "1;31".to_string(),
+            path: "35".to_string(),
+            line_number: "32".to_string(),
+            separator: "36".to_string(),
+        }
+    }
+}
+
+impl Theme {
+    /// Parse a theme like `mt=1;31:fn=35:ln=32:se=36`, in the style of `GREP_COLORS`
+    ///
+    /// `mt` is for matches, `fn` for paths, `ln` for line numbers and `se` for separators.
+    /// Parts that aren't mentioned keep their default color.
+    pub fn parse(spec: &str) -> Result<Theme, String> {
+        let mut theme = Theme::default();
+
+        for entry in spec.split(':').filter(|entry| !entry.is_empty()) {
+            let (key, value) = entry.split_once('=').ok_or_else(|| format!("'{entry}' isn't of the form KEY=COLOR"))?;
+            if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(format!("'{value}' isn't a color like '1;31'"));
+            }
+
+            let color = match key {
+                "mt" => &mut theme.matched,
+                "fn" => &mut theme.path,
+                "ln" => &mut theme.line_number,
+                "se" => &mut theme.separator,
+                _ => return Err(format!("Unknown part '{key}'; expected mt, fn, ln or se")),
+            };
+            *color = value.to_string();
+        }
+
+        Ok(theme)
+    }
+
+    /// Wrap the byte ranges `spans` of `line` in escape codes that color them as matches
+    ///
+    /// `spans` must be sorted and must not overlap.
+    pub fn highlight(&self, line: &str, spans: &[Range<usize>]) -> String {
+        let mut highlighted = String::with_capacity(line.len() + spans.len() * (self.matched.len() + 7));
+        let mut last = 0;
+
+        for span in spans {
+            highlighted.push_str(&line[last..span.start]);
+            // Writing to memory can't fail.
+            let _ = write!(highlighted, "{}", Painted(&self.matched, &line[span.clone()]));
+            last = span.end;
+        }
+        highlighted.push_str(&line[last..]);
+
+        highlighted
+    }
+}
+
+/// Text that is displayed in a color, given as SGR parameters; without a color, it's displayed as is
+pub struct Painted<'a, T>(pub &'a str, pub T);
+
+impl<T: Display> Display for Painted<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Painted(color, text) = self;
+
+        if color.is_empty() {
+            write!(f, "{}", text)
+        } else {
+            write!(f, "\x1b[{}m{}{}", color, text, RESET)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn themes() {
+        let theme = Theme::parse("mt=4;33:ln=:fn=1;34").unwrap();
+
+        assert_eq!("4;33", theme.matched);
+        assert_eq!("1;34", theme.path);
+        assert_eq!("", theme.line_number);
+        assert_eq!("36", theme.separator, "Expected the default for a part that isn't mentioned.");
+        assert_eq!(Theme::default(), Theme::parse("").unwrap());
+
+        assert!(Theme::parse("mt").unwrap_err().contains("KEY=COLOR"));
+        assert!(Theme::parse("mt=red").unwrap_err().contains("'red'"));
+        assert!(Theme::parse("xx=1").unwrap_err().contains("'xx'"));
+    }
+
+    #[test]
+    fn painting() {
+        assert_eq!("\x1b[32m42\x1b[0m", Painted("32", 42).to_string());
+        assert_eq!("42", Painted("", 42).to_string());
+
+        let theme = Theme { matched: "4".to_string(), ..Theme::default() };
+        assert_eq!("a \x1b[4mbird\x1b[0m, a \x1b[4mbird\x1b[0m!", theme.highlight("a bird, a bird!", &[2..6, 10..14]));
+    }
+}
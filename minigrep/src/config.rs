@@ -0,0 +1,716 @@
+use std::env;
+use std::fs;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::color::{Theme, COLORS_ENV};
+
+
+/// The environment variable that turns on case-insensitive search
+pub const IGNORE_CASE_ENV: &str = "IGNORE_CASE";
+
+pub const VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] <QUERY> <PATH>...
+       minigrep [OPTIONS] -e <QUERY>... -f <FILE>... <PATH>...
+       minigrep index [OPTIONS] <DIR>...
+
+Prints the lines that contain QUERY in the files at PATH.
+Directories are searched recursively, and `-` reads standard input.
+
+Options:
+  -e, --regexp <QUERY>      Search for QUERY; can be repeated to search for any of several
+  -f, --file <FILE>         Search for each line of FILE; can be repeated
+  -E, --regex               Treat QUERY as a regular expression
+      --fuzzy <N>           Also match parts of lines up to N typos away from QUERY; closest first, per file
+  -i, --ignore-case         Match regardless of case
+  -s, --case-sensitive      Match case exactly, even if IGNORE_CASE is set
+  -S, --smart-case          Match regardless of case, unless QUERY has an uppercase letter
+  -v, --invert-match        Print the lines that don't match
+  -w, --word-regexp         Only match whole words
+  -n, --line-number         Prefix each line with its line number
+  -c, --count               Print the number of matching lines instead of the lines
+  -l, --files-with-matches  Print the name of the file instead of the lines, if any line matches
+      --replace <TEXT>      Print the matching lines with each match replaced by TEXT
+      --in-place            Replace the matches in the files themselves, instead of printing
+      --dry-run             Print what `--in-place` would change, without changing any file
+      --json                Print one JSON object per line for each match, and a summary at the end
+  -Z, --null                Follow file names with a NUL byte instead of `:` or a newline
+  -A, --after-context <N>   Also print the N lines after each matching line
+  -B, --before-context <N>  Also print the N lines before each matching line
+  -C, --context <N>         Also print the N lines before and after each matching line
+      --color[=WHEN]        Color matches, file names and line numbers: auto, always or never;
+                            without WHEN, always. auto colors when printing to a terminal
+  -H, --with-filename       Prefix each line with its file name
+      --no-filename         Never prefix lines with file names
+      --include <GLOB>      Only search files that match GLOB; can be repeated
+      --exclude <GLOB>      Skip files and directories that match GLOB; can be repeated
+      --hidden              Search hidden files and directories
+      --no-ignore           Don't respect .gitignore and .ignore files
+  -a, --text                Search binary files as if they were text
+      --no-decompress       Search compressed files as they are, without decompressing them
+      --no-index            Search every file, even in directories with an index
+  -j, --threads <N>         Search N files at a time; 0, the default, picks by the number of CPUs
+      --sort                Print files in order of their paths; implies searching them in order
+  -h, --help                Print this help
+  -V, --version             Print the version
+
+Short options can be combined, as in `-in`. `--` ends the options.
+`-l` takes precedence over `-c`, and `--json` over both.
+
+With `-e` or `-f`, all arguments after the options are paths. A line matches
+if any query matches it. Many literal queries are searched for at once, so
+thousands of them, as from `-f`, are about as fast as a few.
+
+With `--json`, each line is a JSON object with a `type`: a `match` has the
+`path`, `line_number`, the byte `offset` of the line in the file, the `line`
+and its `submatches`, each with the `text` and its `start` and `end` byte in
+the line; a `context` line has the `path`, `line_number` and `line`; and the
+`summary` at the end has the totals of `files`, `files_with_matches`,
+`matched_lines` and `matches`.
+
+Context lines are marked with `-` after the file name and line number,
+where matching lines have `:`. Groups of lines that aren't adjacent are
+separated by `--`; overlapping groups are merged.
+
+File names are shown when more than one file can be searched, that is,
+when there are several paths or a directory. Paths given explicitly are
+always searched; the hidden-file, ignore-file and glob rules only apply
+to the files found in directories. Globs match like .gitignore patterns,
+so `*.rs` matches at any depth and `src/*.rs` only below `src`.
+`--exclude` takes precedence over `--include`.
+
+Files are searched in parallel, and the lines of each file are printed
+together. Without `--sort`, files are printed in the order in which their
+search finishes, which can vary from run to run.
+
+In regular expressions, `$1` or `${name}` in the replacement TEXT stand for
+what a capture group matched, and `$$` for `$`. `--in-place` writes each file
+to a temp file first, and renames that over the original; files that aren't
+valid UTF-8 are left alone. `--dry-run` prints each line that would change,
+as `-` with the old line and `+` with the new one.
+
+`--fuzzy` counts inserted, deleted and replaced characters. N has to be less
+than the length of each query, or every line would match. Lines are ranked
+within each file, not across files: the lines of each file are printed
+closest first, and then in order, and the files come in their usual order.
+Context isn't printed. It doesn't work with `-E`.
+
+Files with a NUL byte near the start are taken to be binary and skipped,
+unless `-a` is given. Bytes that aren't valid UTF-8 are shown as U+FFFD.
+
+Files compressed with gzip, bzip2 or Zstandard are recognized by their first
+bytes, whatever their name, and decompressed as they are searched. Line
+numbers and offsets count in the decompressed text. `--in-place` doesn't
+rewrite compressed files, unless `--no-decompress` is given.
+
+`minigrep index DIR` builds an index of the files in DIR, in DIR/.minigrep-index,
+or brings it up to date, reading only the files that changed since. Searches
+of DIR then skip the files that can't match without reading them; files that
+changed since the index was updated, or while it was, are searched anyway.
+The index helps literal queries of three bytes or more, with or without `-i`,
+unless every file has to be searched, as with `-v`, `-c` or `--json`. The walk
+options choose the files to index. To search for the word `index`, use `-e index`.
+
+Environment:
+  IGNORE_CASE               If set, match regardless of case, as with `-i`
+  MINIGREP_COLORS           The colors of `--color`, like `mt=1;31:fn=35:ln=32:se=36`
+                            for matches, file names, line numbers and separators.
+                            Each is a list of ANSI SGR codes; empty means no color.
+                            If it's invalid, the default colors are used, with a warning
+
+Flags take precedence over environment variables: `-s` turns case-insensitive
+matching off even if IGNORE_CASE is set. Of `-i`, `-s` and `-S`, the last one wins.
+
+Literal queries match regardless of case with full Unicode case folding,
+so `strasse` matches `Straße`. Regular expressions fold one character at a time.";
+
+/// When to color the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when the output is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// What to search for; a line matches if any of them does
+    pub patterns: Vec<String>,
+    /// Files with one pattern per line, which `build()` adds to `patterns`
+    pub pattern_files: Vec<String>,
+    /// Files and directories to search
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    /// Ignore case unless the query has an uppercase letter; takes precedence over `ignore_case`
+    pub smart_case: bool,
+    /// Treat the query as a regular expression instead of a literal string
+    pub regex: bool,
+    /// Also match with up to this many edits, and rank lines by their distance
+    pub fuzzy: Option<usize>,
+    /// Select the lines that don't match
+    pub invert_match: bool,
+    /// Only match whole words
+    pub whole_word: bool,
+    pub line_number: bool,
+    /// Print the number of selected lines instead of the lines
+    pub count: bool,
+    /// Print the file path instead of the lines, if any line is selected
+    pub files_with_matches: bool,
+    pub color: ColorChoice,
+    /// The colors to highlight with
+    pub theme: Theme,
+    /// Whether to prefix lines with file names; `None` decides by the paths
+    pub with_filename: Option<bool>,
+    /// Globs of the files to search in directories; empty means all files
+    pub include: Vec<String>,
+    /// Globs of the files and directories to skip in directories
+    pub exclude: Vec<String>,
+    /// Search hidden files and directories
+    pub hidden: bool,
+    /// Don't respect .gitignore and .ignore files
+    pub no_ignore: bool,
+    /// Search binary files as if they were text, instead of skipping them
+    pub text: bool,
+    /// Search compressed files as they are, instead of decompressing them
+    pub no_decompress: bool,
+    /// Build or update the indexes of the paths instead of searching them
+    pub index: bool,
+    /// Search every file, even in directories with an index
+    pub no_index: bool,
+    /// The number of lines to print before each selected line
+    pub before_context: usize,
+    /// The number of lines to print after each selected line
+    pub after_context: usize,
+    /// How many files to search at a time; 0 decides by the number of CPUs
+    pub threads: usize,
+    /// Print the files in the order of their paths
+    pub sort: bool,
+    /// Print JSON events instead of lines
+    pub json: bool,
+    /// Follow file names with a NUL byte instead of `:` or a newline
+    pub null: bool,
+    /// What to replace the matches with
+    pub replace: Option<String>,
+    /// Replace the matches in the files instead of printing them
+    pub in_place: bool,
+    /// With `in_place`, print the changes instead of making them
+    pub dry_run: bool,
+}
+
+impl Config {
+    /// Build the configuration from the program's arguments and environment, and read the pattern files
+    ///
+    /// See `USAGE` for the flags, and for how they interact with the environment.
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
+        let mut config = Config::parse(args, env::var_os(IGNORE_CASE_ENV).is_some())?;
+        config.read_pattern_files()?;
+
+        // A theme that won't be used doesn't matter, and a broken one only costs the colors.
+        if let (Ok(spec), true) = (env::var(COLORS_ENV), config.color != ColorChoice::Never) {
+            config.theme = Theme::parse(&spec).unwrap_or_else(|err| {
+                eprintln!("minigrep: ignoring the invalid {COLORS_ENV} '{spec}': {err}");
+                Theme::default()
+            });
+        }
+
+        Ok(config)
+    }
+
+    /// Add the lines of the pattern files to the patterns
+    ///
+    /// An empty file adds no patterns, and an empty line adds one that matches every line, as with grep.
+    pub fn read_pattern_files(&mut self) -> Result<(), ArgsError> {
+        for file in &self.pattern_files {
+            let contents = fs::read_to_string(file)
+                .map_err(|err| ArgsError::Usage(format!("Couldn't read the pattern file '{file}': {err}")))?;
+            self.patterns.extend(contents.lines().map(str::to_string));
+        }
+
+        self.check_fuzzy_distance()
+    }
+
+    /// Reject a `--fuzzy` distance that every line would be within for some pattern
+    ///
+    /// A pattern of N characters is N edits away from any single character, so with a
+    /// distance of N or more, every non-empty line would match.
+    fn check_fuzzy_distance(&self) -> Result<(), ArgsError> {
+        let Some(distance) = self.fuzzy.filter(|&distance| distance > 0) else {
+            return Ok(());
+        };
+
+        match self.patterns.iter().find(|pattern| distance >= pattern.chars().count()) {
+            Some(pattern) => Err(ArgsError::Usage(format!(
+                "'--fuzzy={distance}' would match every line for the query '{pattern}'; the distance has to be less than its length."
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Build the configuration from the program's arguments
+    ///
+    /// `ignore_case_env` tells whether `IGNORE_CASE_ENV` is set.
+    /// It only sets the default; the flags override it.
+    pub fn parse(mut args: impl Iterator<Item = String>, ignore_case_env: bool) -> Result<Config, ArgsError> {
+        args.next();  // The first argument is the path of the executable (the name of the program).
+
+        let mut config = Config { ignore_case: ignore_case_env, ..Config::default() };
+        let mut args = args.peekable();
+        if args.next_if(|arg| arg == "index").is_some() {
+            config.index = true;
+        }
+        let mut positional = Vec::new();
+        let mut options_ended = false;
+
+        while let Some(arg) = args.next() {
+            if options_ended || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg);
+            } else if arg == "--" {
+                options_ended = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                match long.split_once('=') {
+                    Some((name, value)) => config.set_long(name, Some(value.to_string()), &mut args)?,
+                    None => config.set_long(long, None, &mut args)?,
+                }
+            } else {
+                for (i, flag) in arg.char_indices().skip(1) {
+                    if let Some(name) = value_flag_name(flag) {
+                        // The rest of the argument is the value, as in `-C3`, or else the next argument.
+                        let value = Some(arg[i + 1..].to_string()).filter(|value| !value.is_empty());
+                        config.set_long(name, value.or_else(|| args.next()), &mut args)?;
+                        break;
+                    }
+                    config.set_short(flag)?;
+                }
+            }
+        }
+
+        let mut positional = positional.into_iter();
+
+        // Without `-e` or `-f`, the query comes first.
+        if config.patterns.is_empty() && config.pattern_files.is_empty() && !config.index {
+            match positional.next() {
+                Some(arg) => config.patterns.push(arg),
+                None => return Err(ArgsError::Usage("Didn't get a query string.".to_string())),
+            }
+        }
+
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            let missing = if config.index { "Didn't get a directory to index." } else { "Didn't get a file path string." };
+            return Err(ArgsError::Usage(missing.to_string()));
+        }
+
+        if config.fuzzy.is_some() && config.regex {
+            return Err(ArgsError::Usage("'--fuzzy' doesn't work with regular expressions.".to_string()));
+        }
+        config.check_fuzzy_distance()?;
+
+        if config.in_place && config.replace.is_none() {
+            return Err(ArgsError::Usage("'--in-place' and '--dry-run' need '--replace'.".to_string()));
+        }
+
+        Ok(config)
+    }
+
+    fn set_short(&mut self, flag: char) -> Result<(), ArgsError> {
+        match flag {
+            'E' => self.regex = true,
+            'i' => (self.ignore_case, self.smart_case) = (true, false),
+            's' => (self.ignore_case, self.smart_case) = (false, false),
+            'S' => self.smart_case = true,
+            'v' => self.invert_match = true,
+            'w' => self.whole_word = true,
+            'n' => self.line_number = true,
+            'c' => self.count = true,
+            'l' => self.files_with_matches = true,
+            'H' => self.with_filename = Some(true),
+            'a' => self.text = true,
+            'Z' => self.null = true,
+            'h' => return Err(ArgsError::Help),
+            'V' => return Err(ArgsError::Version),
+            _ => return Err(ArgsError::Usage(format!("Unknown flag '-{flag}'."))),
+        }
+
+        Ok(())
+    }
+
+    /// Set the long option `name`
+    ///
+    /// `value` is the value given after `=`, if any. Options that need a value
+    /// take the next argument when there was no `=`.
+    fn set_long(&mut self, name: &str, value: Option<String>, args: &mut impl Iterator<Item = String>) -> Result<(), ArgsError> {
+        let mut required_value = || {
+            value.clone().or_else(|| args.next()).ok_or_else(|| ArgsError::Usage(format!("The flag '--{name}' needs a value.")))
+        };
+
+        match name {
+            "color" | "colour" => {
+                self.color = match value.as_deref() {
+                    None | Some("always") => ColorChoice::Always,
+                    Some("auto") => ColorChoice::Auto,
+                    Some("never") => ColorChoice::Never,
+                    Some(value) => {
+                        return Err(ArgsError::Usage(format!("Invalid value '{value}' for '--color'; expected auto, always or never.")));
+                    }
+                };
+                return Ok(());
+            }
+            "include" => {
+                self.include.push(required_value()?);
+                return Ok(());
+            }
+            "exclude" => {
+                self.exclude.push(required_value()?);
+                return Ok(());
+            }
+            "after-context" | "before-context" | "context" => {
+                let value = required_value()?;
+                let lines = value.parse().map_err(|_| ArgsError::Usage(format!("Invalid number of lines '{value}' for '--{name}'.")))?;
+                if name != "before-context" {
+                    self.after_context = lines;
+                }
+                if name != "after-context" {
+                    self.before_context = lines;
+                }
+                return Ok(());
+            }
+            "regexp" => {
+                self.patterns.push(required_value()?);
+                return Ok(());
+            }
+            "file" => {
+                self.pattern_files.push(required_value()?);
+                return Ok(());
+            }
+            "fuzzy" => {
+                let value = required_value()?;
+                let distance = value.parse().map_err(|_| ArgsError::Usage(format!("Invalid distance '{value}' for '--fuzzy'.")))?;
+                self.fuzzy = Some(distance);
+                return Ok(());
+            }
+            "replace" => {
+                self.replace = Some(required_value()?);
+                return Ok(());
+            }
+            "threads" => {
+                let value = required_value()?;
+                self.threads = value.parse().map_err(|_| ArgsError::Usage(format!("Invalid number of threads '{value}' for '--threads'.")))?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if value.is_some() {
+            return Err(ArgsError::Usage(format!("The flag '--{name}' doesn't take a value.")));
+        }
+
+        match name {
+            "no-filename" => self.with_filename = Some(false),
+            "hidden" => self.hidden = true,
+            "no-ignore" => self.no_ignore = true,
+            "no-decompress" => self.no_decompress = true,
+            "no-index" => self.no_index = true,
+            "sort" => self.sort = true,
+            "json" => self.json = true,
+            "in-place" => self.in_place = true,
+            "dry-run" => (self.in_place, self.dry_run) = (true, true),
+            _ => {
+                let flag = match name {
+                    "regex" => 'E',
+                    "ignore-case" => 'i',
+                    "case-sensitive" => 's',
+                    "smart-case" => 'S',
+                    "invert-match" => 'v',
+                    "word-regexp" => 'w',
+                    "line-number" => 'n',
+                    "count" => 'c',
+                    "files-with-matches" => 'l',
+                    "with-filename" => 'H',
+                    "text" => 'a',
+                    "null" => 'Z',
+                    "help" => 'h',
+                    "version" => 'V',
+                    _ => return Err(ArgsError::Usage(format!("Unknown flag '--{name}'."))),
+                };
+                self.set_short(flag)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The long name of a short flag that takes a value
+fn value_flag_name(flag: char) -> Option<&'static str> {
+    match flag {
+        'A' => Some("after-context"),
+        'B' => Some("before-context"),
+        'C' => Some("context"),
+        'j' => Some("threads"),
+        'e' => Some("regexp"),
+        'f' => Some("file"),
+        _ => None,
+    }
+}
+
+/// Why the arguments didn't produce a `Config`
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArgsError {
+    /// `--help` was given; print `USAGE`
+    Help,
+    /// `--version` was given; print `VERSION`
+    Version,
+    /// The arguments are wrong
+    Usage(String),
+}
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{USAGE}"),
+            ArgsError::Version => write!(f, "{VERSION}"),
+            ArgsError::Usage(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, ArgsError> {
+        let args = ["minigrep"].iter().chain(args).map(|arg| arg.to_string());
+        Config::parse(args, false)
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let config = parse(&["bird", "poem.txt"]).unwrap();
+
+        assert_eq!(vec!["bird"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        assert_eq!(Config { patterns: vec!["bird".to_string()], paths: vec!["poem.txt".to_string()], ..Config::default() }, config);
+
+        let config = parse(&["bird", "poem.txt", "src", "-"]).unwrap();
+        assert_eq!(vec!["poem.txt", "src", "-"], config.paths);
+    }
+
+    #[test]
+    fn index_command() {
+        let config = parse(&["index", "--hidden", "src", "docs"]).unwrap();
+
+        assert!(config.index);
+        assert!(config.hidden);
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["src", "docs"], config.paths);
+
+        let config = parse(&["-e", "index", "src"]).unwrap();
+        assert!(!config.index);
+        assert_eq!(vec!["index"], config.patterns);
+        assert!(!parse(&["-i", "index", "src"]).unwrap().index, "Expected `index` to only be a command first.");
+        assert!(parse(&["--no-index", "bird", "src"]).unwrap().no_index);
+
+        assert_eq!(Err(ArgsError::Usage("Didn't get a directory to index.".to_string())), parse(&["index"]));
+    }
+
+    #[test]
+    fn missing_arguments() {
+        assert_eq!(Err(ArgsError::Usage("Didn't get a query string.".to_string())), parse(&["-i"]));
+        assert_eq!(Err(ArgsError::Usage("Didn't get a file path string.".to_string())), parse(&["bird"]));
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config = parse(&["-in", "--invert-match", "bird", "-w", "poem.txt", "--count", "-l", "-E"]).unwrap();
+
+        assert!(config.ignore_case);
+        assert!(config.line_number);
+        assert!(config.invert_match);
+        assert!(config.whole_word);
+        assert!(config.count);
+        assert!(config.files_with_matches);
+        assert!(config.regex);
+        assert!(parse(&["--regex", "bird", "poem.txt"]).unwrap().regex);
+        assert_eq!(vec!["bird"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn walk_options() {
+        let config = parse(&["--include", "*.rs", "--include=*.toml", "--exclude=target", "--hidden", "--no-ignore", "-H", "fn", "."]).unwrap();
+
+        assert_eq!(vec!["*.rs", "*.toml"], config.include);
+        assert_eq!(vec!["target"], config.exclude);
+        assert!(config.hidden);
+        assert!(config.no_ignore);
+        assert!(!config.text);
+        assert!(!config.no_decompress);
+        assert!(parse(&["--no-decompress", "fn", "."]).unwrap().no_decompress);
+        assert_eq!(Some(true), config.with_filename);
+        assert_eq!(Some(false), parse(&["-H", "--no-filename", "fn", "."]).unwrap().with_filename);
+
+        assert_eq!(Err(ArgsError::Usage("The flag '--include' needs a value.".to_string())), parse(&["fn", ".", "--include"]));
+    }
+
+    #[test]
+    fn text_and_stdin() {
+        let config = parse(&["-a", "bird", "-"]).unwrap();
+
+        assert!(config.text);
+        assert_eq!(vec!["-"], config.paths);
+        assert!(parse(&["--text", "bird", "-"]).unwrap().text);
+    }
+
+    #[test]
+    fn context_lengths() {
+        let config = parse(&["-A", "2", "-B3", "bird", "poem.txt"]).unwrap();
+        assert_eq!((3, 2), (config.before_context, config.after_context));
+
+        let config = parse(&["-nC1", "bird", "poem.txt", "--after-context=4"]).unwrap();
+        assert!(config.line_number);
+        assert_eq!((1, 4), (config.before_context, config.after_context));
+
+        assert_eq!((5, 5), {
+            let config = parse(&["--context", "5", "bird", "poem.txt"]).unwrap();
+            (config.before_context, config.after_context)
+        });
+
+        assert_eq!(Err(ArgsError::Usage("Invalid number of lines 'x' for '--after-context'.".to_string())), parse(&["-Ax", "bird", "poem.txt"]));
+        assert_eq!(Err(ArgsError::Usage("The flag '--context' needs a value.".to_string())), parse(&["-C"]));
+    }
+
+    #[test]
+    fn threads_and_sort() {
+        let config = parse(&["bird", "src"]).unwrap();
+        assert_eq!((0, false), (config.threads, config.sort));
+
+        let config = parse(&["-j4", "--sort", "bird", "src"]).unwrap();
+        assert_eq!((4, true), (config.threads, config.sort));
+        assert_eq!(1, parse(&["--threads=1", "bird", "src"]).unwrap().threads);
+
+        assert!(matches!(parse(&["-j", "many", "bird", "src"]), Err(ArgsError::Usage(_))));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(parse(&["-S", "bird", "poem.txt"]).unwrap().smart_case);
+        assert!(!parse(&["--smart-case", "-i", "bird", "poem.txt"]).unwrap().smart_case);
+        assert!(!parse(&["-S", "-s", "bird", "poem.txt"]).unwrap().smart_case);
+
+        let config = parse(&["-i", "-S", "bird", "poem.txt"]).unwrap();
+        assert!(config.ignore_case && config.smart_case);
+    }
+
+    #[test]
+    fn output_formats() {
+        let config = parse(&["--json", "-Z", "bird", "poem.txt"]).unwrap();
+        assert!(config.json);
+        assert!(config.null);
+        assert!(parse(&["--null", "bird", "poem.txt"]).unwrap().null);
+    }
+
+    #[test]
+    fn replace_options() {
+        let config = parse(&["--replace", "$1", "--in-place", "-E", "(bird)", "poem.txt"]).unwrap();
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(config.in_place && !config.dry_run);
+
+        let config = parse(&["--replace=", "--dry-run", "bird", "poem.txt"]).unwrap();
+        assert_eq!(Some(String::new()), config.replace);
+        assert!(config.in_place && config.dry_run);
+
+        assert_eq!(Err(ArgsError::Usage("'--in-place' and '--dry-run' need '--replace'.".to_string())), parse(&["--in-place", "bird", "poem.txt"]));
+    }
+
+    #[test]
+    fn several_patterns() {
+        let config = parse(&["-e", "bird", "-iebird", "--regexp=swan", "-f", "patterns.txt", "poem.txt", "notes.txt"]).unwrap();
+
+        assert_eq!(vec!["bird", "bird", "swan"], config.patterns);
+        assert_eq!(vec!["patterns.txt"], config.pattern_files);
+        assert_eq!(vec!["poem.txt", "notes.txt"], config.paths);
+        assert!(config.ignore_case);
+
+        // A pattern that looks like a flag
+        assert_eq!(vec!["-v"], parse(&["-e", "-v", "poem.txt"]).unwrap().patterns);
+
+        assert_eq!(Err(ArgsError::Usage("Didn't get a file path string.".to_string())), parse(&["-e", "bird"]));
+    }
+
+    #[test]
+    fn pattern_files() {
+        let path = env::temp_dir().join(format!("minigrep-patterns-{}.txt", std::process::id()));
+        fs::write(&path, "bird\r\nswan\n").unwrap();
+
+        let mut config = parse(&["-e", "owl", "-f", path.to_str().unwrap(), "poem.txt"]).unwrap();
+        config.read_pattern_files().unwrap();
+        assert_eq!(vec!["owl", "bird", "swan"], config.patterns);
+
+        fs::remove_file(&path).unwrap();
+
+        let mut config = parse(&["-f", "no-such-patterns.txt", "poem.txt"]).unwrap();
+        assert!(matches!(config.read_pattern_files(), Err(ArgsError::Usage(_))));
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(None, parse(&["bird", "poem.txt"]).unwrap().fuzzy);
+        assert_eq!(Some(2), parse(&["--fuzzy=2", "bird", "poem.txt"]).unwrap().fuzzy);
+        assert_eq!(Some(0), parse(&["--fuzzy", "0", "bird", "poem.txt"]).unwrap().fuzzy);
+
+        assert!(matches!(parse(&["--fuzzy=-1", "bird", "poem.txt"]), Err(ArgsError::Usage(_))));
+        assert!(matches!(parse(&["--fuzzy=1", "-E", "bird", "poem.txt"]), Err(ArgsError::Usage(_))));
+        assert!(matches!(parse(&["--fuzzy=2", "ab", "poem.txt"]), Err(ArgsError::Usage(_))), "Expected every line to be too close.");
+        assert!(matches!(parse(&["--fuzzy=3", "-e", "bird", "-e", "owl", "poem.txt"]), Err(ArgsError::Usage(_))));
+        assert_eq!(Some(1), parse(&["--fuzzy=1", "ab", "poem.txt"]).unwrap().fuzzy);
+    }
+
+    #[test]
+    fn options_end_at_double_dash() {
+        let config = parse(&["-n", "--", "-v", "--help"]).unwrap();
+
+        assert!(config.line_number);
+        assert!(!config.invert_match);
+        assert_eq!(vec!["-v"], config.patterns);
+        assert_eq!(vec!["--help"], config.paths);
+    }
+
+    #[test]
+    fn color_values() {
+        assert_eq!(ColorChoice::Auto, parse(&["bird", "poem.txt"]).unwrap().color);
+        assert_eq!(ColorChoice::Always, parse(&["--color", "bird", "poem.txt"]).unwrap().color);
+        assert_eq!(ColorChoice::Never, parse(&["--color=never", "bird", "poem.txt"]).unwrap().color);
+        assert!(matches!(parse(&["--color=sometimes", "bird", "poem.txt"]), Err(ArgsError::Usage(_))));
+    }
+
+    #[test]
+    fn unknown_flags() {
+        assert_eq!(Err(ArgsError::Usage("Unknown flag '-x'.".to_string())), parse(&["-ix", "bird", "poem.txt"]));
+        assert_eq!(Err(ArgsError::Usage("Unknown flag '--fast'.".to_string())), parse(&["--fast", "bird", "poem.txt"]));
+        assert!(matches!(parse(&["--count=2", "bird", "poem.txt"]), Err(ArgsError::Usage(_))));
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(Err(ArgsError::Help), parse(&["--help"]));
+        assert_eq!(Err(ArgsError::Help), parse(&["bird", "-h", "poem.txt"]));
+        assert_eq!(Err(ArgsError::Version), parse(&["-V"]));
+    }
+
+    #[test]
+    fn flags_override_environment() {
+        let args = |args: &[&str]| ["minigrep"].iter().chain(args).map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert!(Config::parse(args(&["bird", "poem.txt"]).into_iter(), true).unwrap().ignore_case);
+        assert!(!Config::parse(args(&["-s", "bird", "poem.txt"]).into_iter(), true).unwrap().ignore_case);
+        assert!(Config::parse(args(&["-s", "-i", "bird", "poem.txt"]).into_iter(), false).unwrap().ignore_case);
+        assert!(!Config::parse(args(&["-i", "-s", "bird", "poem.txt"]).into_iter(), false).unwrap().ignore_case);
+    }
+}
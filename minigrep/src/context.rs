@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+
+
+/// What to print for a line of the input
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// `--`, between groups of lines that aren't adjacent
+    Separator,
+    /// A selected line
+    Match(Line<'a>),
+    /// A line around a selected line
+    Context(Line<'a>),
+}
+
+/// A line of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// Counting from 1
+    pub number: usize,
+    /// Where the line starts in the input, in bytes
+    pub offset: u64,
+    /// Without the line ending
+    pub text: &'a str,
+}
+
+/// Decides which lines around the selected lines to print
+///
+/// Lines are pushed in order. Up to `before` lines are held back, in case a selected
+/// line follows; no more are kept in memory. Windows that overlap or touch are merged
+/// into one group, so every line is printed at most once.
+pub struct Context {
+    before: usize,
+    after: usize,
+    /// The lines that may still be printed as context before the next selected line
+    buffer: VecDeque<(usize, u64, String)>,
+    /// How many more lines to print as context after the last selected line
+    after_left: usize,
+    /// The number of the last line that was printed
+    last_printed: Option<usize>,
+}
+
+impl Context {
+    pub fn new(before: usize, after: usize) -> Context {
+        Context { before, after, buffer: VecDeque::with_capacity(before), after_left: 0, last_printed: None }
+    }
+
+    /// Push the next line, and `emit` what to print for it, if anything
+    ///
+    /// Buffered lines come back with the offset that they were pushed with.
+    ///
+    /// `selected` tells whether the line matched (or, with `-v`, didn't).
+    pub fn push<E>(&mut self, line: Line, selected: bool, mut emit: impl FnMut(Event) -> Result<(), E>) -> Result<(), E> {
+        if selected {
+            // Without context, every line would be a group of its own; grep doesn't separate those.
+            let first = self.buffer.front().map_or(line.number, |(number, ..)| *number);
+            let has_context = self.before > 0 || self.after > 0;
+            if has_context && self.last_printed.is_some_and(|last| last + 1 < first) {
+                emit(Event::Separator)?;
+            }
+
+            for (number, offset, text) in self.buffer.drain(..) {
+                emit(Event::Context(Line { number, offset, text: &text }))?;
+            }
+            emit(Event::Match(line))?;
+
+            self.after_left = self.after;
+            self.last_printed = Some(line.number);
+        } else if self.after_left > 0 {
+            emit(Event::Context(line))?;
+
+            self.after_left -= 1;
+            self.last_printed = Some(line.number);
+        } else if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back((line.number, line.offset, line.text.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The output for `lines`, where the selected lines start with `>`, as `:` for matches and `-` for context
+    fn run(before: usize, after: usize, lines: &[&str]) -> Vec<String> {
+        let mut context = Context::new(before, after);
+        let mut output = Vec::new();
+
+        for (i, text) in lines.iter().enumerate() {
+            let line = Line { number: i + 1, offset: 0, text };
+            context
+                .push(line, text.starts_with('>'), |event| {
+                    output.push(match event {
+                        Event::Separator => "--".to_string(),
+                        Event::Match(line) => format!("{}:{}", line.number, line.text),
+                        Event::Context(line) => format!("{}-{}", line.number, line.text),
+                    });
+                    Ok::<(), ()>(())
+                })
+                .unwrap();
+        }
+
+        output
+    }
+
+    const LINES: [&str; 10] = ["a", ">b", "c", "d", "e", "f", "g", ">h", "i", "j"];
+
+    #[test]
+    fn no_context() {
+        assert_eq!(vec!["2:>b", "8:>h"], run(0, 0, &LINES));
+    }
+
+    #[test]
+    fn separated_groups() {
+        assert_eq!(vec!["1-a", "2:>b", "3-c", "--", "7-g", "8:>h", "9-i"], run(1, 1, &LINES));
+        assert_eq!(vec!["2:>b", "3-c", "4-d", "--", "8:>h", "9-i", "10-j"], run(0, 2, &LINES));
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        assert_eq!(vec!["1-a", "2:>b", "3-c", "4-d", "5-e", "6-f", "7-g", "8:>h", "9-i", "10-j"], run(3, 3, &LINES));
+
+        // Windows that only touch are merged, too.
+        assert_eq!(vec!["2:>b", "3-c", "4-d", "5-e", "6-f", "7-g", "8:>h"], run(2, 3, &LINES)[1..8]);
+    }
+
+    #[test]
+    fn adjacent_matches() {
+        assert_eq!(vec!["1-a", "2:>b", "3:>c", "4-d"], run(1, 1, &["a", ">b", ">c", "d", "e"]));
+    }
+}
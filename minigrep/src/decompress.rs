@@ -0,0 +1,132 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use ruzstd::decoding::{FrameDecoder, StreamingDecoder};
+
+
+/// A compression format, recognized by the magic bytes at the start of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Bzip2,
+    Zstd,
+}
+
+impl Format {
+    /// The format of input that starts with `bytes`, or `None` if it isn't compressed
+    pub fn detect(bytes: &[u8]) -> Option<Format> {
+        match bytes {
+            [0x1f, 0x8b, ..] => Some(Format::Gzip),
+            // The fourth byte is the block size, from 1 to 9.
+            [b'B', b'Z', b'h', b'1'..=b'9', ..] => Some(Format::Bzip2),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Format::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Wrap `reader` in a decoder for its compression format, if it's compressed
+///
+/// Nothing is consumed to find out, and decompression streams as the result is read.
+/// Concatenated archives, as `cat` makes them of rotated logs, are decompressed as one.
+pub fn decompressed<'a>(mut reader: Box<dyn BufRead + 'a>, buffer_size: usize) -> io::Result<Box<dyn BufRead + 'a>> {
+    let decoder: Box<dyn Read + 'a> = match Format::detect(reader.fill_buf()?) {
+        None => return Ok(reader),
+        Some(Format::Gzip) => Box::new(MultiGzDecoder::new(reader)),
+        Some(Format::Bzip2) => Box::new(MultiBzDecoder::new(reader)),
+        Some(Format::Zstd) => Box::new(ZstdFrames { source: Some(reader), decoder: None }),
+    };
+
+    Ok(Box::new(BufReader::with_capacity(buffer_size, decoder)))
+}
+
+/// Decodes Zstandard frames one after the other, for as long as there is input
+///
+/// The decoder of the `ruzstd` crate stops at the end of the first frame.
+struct ZstdFrames<R: BufRead> {
+    /// The input between frames; `None` while a frame is decoded, or after a broken one
+    source: Option<R>,
+    decoder: Option<StreamingDecoder<R, FrameDecoder>>,
+}
+
+impl<R: BufRead> Read for ZstdFrames<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(decoder) = &mut self.decoder {
+                let len = decoder.read(buf)?;
+                if len > 0 || buf.is_empty() {
+                    return Ok(len);
+                }
+
+                // The frame is over; another may follow.
+                let (source, _) = self.decoder.take().expect("Expected a decoder.").into_parts();
+                self.source = Some(source);
+            }
+
+            let Some(mut source) = self.source.take() else {
+                return Ok(0);
+            };
+            if source.fill_buf()?.is_empty() {
+                self.source = Some(source);
+                return Ok(0);
+            }
+            self.decoder = Some(StreamingDecoder::new(source).map_err(io::Error::other)?);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const TEXT: &[u8] = b"Is there a bird here?\nPerhaps a flamingo?\n";
+
+    fn read(bytes: &[u8]) -> Vec<u8> {
+        let mut decompressed = Vec::new();
+        super::decompressed(Box::new(bytes), 1024).unwrap().read_to_end(&mut decompressed).unwrap();
+        decompressed
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bzip2(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Some(Format::Gzip), Format::detect(&gzip(TEXT)));
+        assert_eq!(Some(Format::Bzip2), Format::detect(&bzip2(TEXT)));
+        assert_eq!(Some(Format::Zstd), Format::detect(&ruzstd::encoding::compress_to_vec(TEXT, ruzstd::encoding::CompressionLevel::Fastest)));
+        assert_eq!(None, Format::detect(b"BZh, said the bee"));
+        assert_eq!(None, Format::detect(TEXT));
+        assert_eq!(None, Format::detect(b""));
+    }
+
+    #[test]
+    fn decompression() {
+        assert_eq!(TEXT, read(&gzip(TEXT)));
+        assert_eq!(TEXT, read(&bzip2(TEXT)));
+        assert_eq!(TEXT, read(TEXT), "Expected uncompressed input to stay as it is.");
+    }
+
+    #[test]
+    fn concatenated_archives() {
+        let zstd = |bytes| ruzstd::encoding::compress_to_vec(bytes, ruzstd::encoding::CompressionLevel::Fastest);
+        let doubled = [TEXT, TEXT].concat();
+
+        assert_eq!(doubled, read(&[gzip(TEXT), gzip(TEXT)].concat()));
+        assert_eq!(doubled, read(&[bzip2(TEXT), bzip2(TEXT)].concat()));
+        assert_eq!(doubled, read(&[zstd(TEXT), zstd(TEXT)].concat()));
+    }
+}
@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::iter;
+use std::ops::Range;
+
+use aho_corasick::{AhoCorasick, BuildError, Input, MatchKind};
+use caseless::Caseless;
+
+
+/// A literal query that matches regardless of case, with full Unicode case folding
+///
+/// Full folding maps some characters to several: `ß` matches `ss` and `SS`, and `İ`
+/// (the Turkish capital dotted I) matches `i̇`, an `i` with a combining dot above.
+/// Lines are folded one character at a time as they are compared, so nothing is
+/// allocated per line. Matches start and end on character boundaries of the line;
+/// `s` doesn't match half of a `ß`.
+pub struct FoldedQuery {
+    chars: Vec<char>,
+}
+
+impl FoldedQuery {
+    pub fn new(query: &str) -> FoldedQuery {
+        FoldedQuery { chars: query.chars().default_case_fold().collect() }
+    }
+
+    /// The first match in `line` that starts at or after the byte offset `start`
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        if self.chars.is_empty() {
+            return Some(start..start);
+        }
+
+        line[start..]
+            .char_indices()
+            .find_map(|(i, _)| self.match_len(&line[start + i..]).map(|len| start + i..start + i + len))
+    }
+
+    /// The length in bytes of the match at the start of `text`, if there is one
+    fn match_len(&self, text: &str) -> Option<usize> {
+        let mut query = self.chars.iter();
+
+        for (i, c) in text.char_indices() {
+            for folded in iter::once(c).default_case_fold() {
+                if query.next() != Some(&folded) {
+                    return None;
+                }
+            }
+
+            if query.len() == 0 {
+                return Some(i + c.len_utf8());
+            }
+        }
+
+        None
+    }
+}
+
+/// Many literal queries that match regardless of case, with full Unicode case folding
+///
+/// The queries are folded into one Aho-Corasick automaton, which runs over the folded line,
+/// so thousands of queries cost about as much as one. Each thread folds lines into the same
+/// buffer over and over, so nothing is allocated per line once the buffer is big enough.
+/// Like with `FoldedQuery`, matches start and end on character boundaries of the line.
+pub struct FoldedSet {
+    automaton: AhoCorasick,
+}
+
+/// Marks a byte of a folded line that doesn't start the folding of a character of the line
+const NOT_A_BOUNDARY: usize = usize::MAX;
+
+thread_local! {
+    /// A folded line, and for each of its bytes, the offset in the line of the character
+    /// whose folding starts there, or `NOT_A_BOUNDARY`; the last entry is the end of the line.
+    static FOLDED_LINE: RefCell<(String, Vec<usize>)> = const { RefCell::new((String::new(), Vec::new())) };
+}
+
+impl FoldedSet {
+    /// Build the automaton; fails if there are too many queries for it
+    pub fn new(queries: &[String]) -> Result<FoldedSet, BuildError> {
+        let folded = queries.iter().map(|query| query.chars().default_case_fold().collect::<String>());
+        let automaton = AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(folded)?;
+
+        Ok(FoldedSet { automaton })
+    }
+
+    /// The first match of any of the queries in `line` that starts at or after the byte offset `start`
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        FOLDED_LINE.with(|folded| {
+            let (text, origins) = &mut *folded.borrow_mut();
+            text.clear();
+            origins.clear();
+
+            for (i, c) in line[start..].char_indices() {
+                text.extend(iter::once(c).default_case_fold());
+                origins.push(start + i);
+                origins.resize(text.len(), NOT_A_BOUNDARY);
+            }
+            origins.push(line.len());
+
+            // Matches don't overlap, so after one that doesn't start and end on boundaries,
+            // the search starts over at the next boundary, where an overlapping one may start.
+            let mut next = 0;
+            loop {
+                let found = self.automaton.find(Input::new(text.as_str()).span(next..text.len()))?;
+                let (start, end) = (origins[found.start()], origins[found.end()]);
+                if start != NOT_A_BOUNDARY && end != NOT_A_BOUNDARY {
+                    return Some(start..end);
+                }
+
+                next = found.start() + 1;
+                while origins[next] == NOT_A_BOUNDARY {
+                    next += 1;
+                }
+            }
+        })
+    }
+}
+
+/// Check whether `query` has an uppercase letter, for smart case
+///
+/// In a regular expression, escapes like `\W` or `\S` don't count.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+
+    false
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, line: &str) -> Option<Range<usize>> {
+        FoldedQuery::new(query).find_at(line, 0)
+    }
+
+    #[test]
+    fn simple_folding() {
+        assert_eq!(Some(9..13), find("bird", "Is there BiRd here?"));
+        assert_eq!(Some(0..10), find("ΣΟΦΙΑ", "σοφια"));
+        assert_eq!(None, find("bird", "Is there a swan?"));
+    }
+
+    #[test]
+    fn full_folding() {
+        assert_eq!(Some(2..4), find("ss", "Fuß ball"), "Expected ß to match ss.");
+        assert_eq!(Some(2..9), find("STRASSE", "A straße"));
+        assert_eq!(Some(0..2), find("ß", "SSbird"));
+        assert_eq!(Some(0..9), find("i\u{307}stanbul", "İSTANBUL"));
+        assert_eq!(Some(0..10), find("İstanbul", "i\u{307}stanbul"));
+    }
+
+    #[test]
+    fn matches_end_on_character_boundaries() {
+        assert_eq!(None, find("s", "ß"));
+        assert_eq!(None, find("fus", "Fuß"));
+        assert_eq!(Some(5..6), find("s", "Fuß s"));
+    }
+
+    #[test]
+    fn find_from_offset() {
+        let query = FoldedQuery::new("BIRD");
+        assert_eq!(Some(6..10), query.find_at("bird, Bird", 4));
+        assert_eq!(None, query.find_at("bird", 1));
+    }
+
+    #[test]
+    fn sets_of_queries() {
+        let set = FoldedSet::new(&["SWAN".to_string(), "strasse".to_string(), "bird".to_string(), "birdhouse".to_string()]).unwrap();
+
+        assert_eq!(Some(2..6), set.find_at("A BiRd here", 0));
+        assert_eq!(Some(2..11), set.find_at("A BirdHouse", 0), "Expected the longest match.");
+        assert_eq!(Some(9..16), set.find_at("Die Fuß-Straße", 0));
+        assert_eq!(Some(10..14), set.find_at("a swan, a Swan", 4));
+        assert_eq!(None, set.find_at("A flamingo", 0));
+    }
+
+    #[test]
+    fn set_matches_end_on_character_boundaries() {
+        let set = FoldedSet::new(&["s".to_string()]).unwrap();
+
+        assert_eq!(None, set.find_at("ß", 0));
+        assert_eq!(Some(5..6), set.find_at("Fuß s", 0));
+
+        let set = FoldedSet::new(&["sb".to_string(), "bird".to_string()]).unwrap();
+        assert_eq!(Some(2..6), set.find_at("ßbird", 0), "Expected a match that overlaps a rejected one.");
+    }
+
+    #[test]
+    fn uppercase_for_smart_case() {
+        assert!(has_uppercase("Bird", false));
+        assert!(has_uppercase("straßE", false));
+        assert!(!has_uppercase("bird", false));
+        assert!(!has_uppercase(r"\w+\S", true));
+        assert!(has_uppercase(r"\w+\S", false));
+        assert!(has_uppercase(r"\wB", true));
+    }
+}
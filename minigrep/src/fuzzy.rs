@@ -0,0 +1,141 @@
+use std::iter;
+use std::ops::Range;
+
+use caseless::Caseless;
+
+
+/// A literal query that also matches with a few typos
+///
+/// A part of a line matches if it can be turned into the query with at most
+/// `max_distance` edits, each inserting, deleting or substituting one character
+/// (the Levenshtein distance). Where the match starts in the line doesn't matter.
+pub struct FuzzyQuery {
+    chars: Vec<char>,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+impl FuzzyQuery {
+    pub fn new(query: &str, max_distance: usize, ignore_case: bool) -> FuzzyQuery {
+        FuzzyQuery { chars: query.chars().collect(), max_distance, ignore_case }
+    }
+
+    /// The smallest distance between the query and any part of `line`, if it's at most `max_distance`
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        let mut best = None;
+
+        self.scan(line, 0, |distance, _| {
+            if best.is_none_or(|best| distance < best) {
+                best = Some(distance);
+            }
+            // Nothing beats an exact match.
+            distance > 0
+        });
+
+        best.filter(|&distance| distance <= self.max_distance)
+    }
+
+    /// The first match in `line` that starts at or after the byte offset `start`, with its distance
+    ///
+    /// Once a match is found, it's extended for as long as that doesn't make the distance worse,
+    /// so that the query `bird` finds all of `bird`, and not just `bir`, one edit away.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, Range<usize>)> {
+        let mut found: Option<(usize, Range<usize>)> = None;
+
+        self.scan(line, start, |distance, span| match &found {
+            None if distance <= self.max_distance => {
+                found = Some((distance, span));
+                true
+            }
+            None => true,
+            Some((best, _)) if distance <= *best => {
+                found = Some((distance, span));
+                true
+            }
+            Some(_) => false,
+        });
+
+        found
+    }
+
+    /// Compute the distance of the best match that ends after each character of `line[start..]`
+    ///
+    /// `visit` gets the distance and the span of the match, and returns whether to go on.
+    /// This is the classic dynamic programming over the query, where a match may start anywhere
+    /// for free; along with each distance, it remembers where its alignment started.
+    fn scan(&self, line: &str, start: usize, mut visit: impl FnMut(usize, Range<usize>) -> bool) {
+        let len = self.chars.len();
+        // The distances and start offsets for each prefix of the query, up to the previous character.
+        let mut previous: Vec<(usize, usize)> = (0..=len).map(|j| (j, start)).collect();
+        let mut current = previous.clone();
+
+        for (i, c) in line[start..].char_indices() {
+            let end = start + i + c.len_utf8();
+            current[0] = (0, end);
+
+            for j in 1..=len {
+                let cost = usize::from(!self.same(self.chars[j - 1], c));
+                let substitute = (previous[j - 1].0 + cost, previous[j - 1].1);
+                let delete = (previous[j].0 + 1, previous[j].1);
+                let insert = (current[j - 1].0 + 1, current[j - 1].1);
+
+                // On ties, prefer the alignment that started first.
+                current[j] = [substitute, delete, insert].into_iter().min_by_key(|&(distance, from)| (distance, from)).unwrap();
+            }
+
+            let (distance, from) = current[len];
+            if !visit(distance, from..end) {
+                return;
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+    }
+
+    /// Whether `a` and `b` are the same character, after case folding if case is ignored
+    ///
+    /// The folding is the one `-i` uses, so `ſ` (the long s) is the same as `s`.
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && iter::once(a).default_case_fold().eq(iter::once(b).default_case_fold()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        let query = FuzzyQuery::new("bird", 2, false);
+
+        assert_eq!(Some(0), query.distance("Is there a bird here?"));
+        assert_eq!(Some(2), query.distance("Is there a brid here?"), "Expected a transposition to be two edits.");
+        assert_eq!(Some(1), query.distance("Is there a bord here?"));
+        assert_eq!(Some(1), query.distance("Is there a bid here?"));
+        assert_eq!(Some(2), query.distance("Is there a Bid here?"));
+        assert_eq!(None, query.distance("Perhaps a flamingo?"));
+        assert_eq!(Some(1), FuzzyQuery::new("bird", 2, true).distance("Is there a Bid here?"));
+        assert_eq!(Some(0), FuzzyQuery::new("swan", 1, true).distance("Or a ſwan?"), "Expected case folding like -i.");
+    }
+
+    #[test]
+    fn spans() {
+        let query = FuzzyQuery::new("bird", 1, false);
+
+        assert_eq!(Some((0, 11..15)), query.find_at("Is there a bird here?", 0));
+        assert_eq!(Some((1, 11..15)), query.find_at("Is there a bord, a bird?", 0));
+        assert_eq!(Some((0, 19..23)), query.find_at("Is there a bord, a bird?", 15));
+        assert_eq!(Some((1, 2..5)), query.find_at("a bid", 0));
+        assert_eq!(Some((1, 2..6)), query.find_at("a birb", 0));
+        assert_eq!(None, query.find_at("a bat", 0));
+    }
+
+    #[test]
+    fn characters_not_bytes() {
+        let query = FuzzyQuery::new("straße", 2, true);
+
+        assert_eq!(Some(2), query.distance("Die Strasse"));
+        assert_eq!(Some((0, 4..12)), query.find_at("Die Straẞe", 0));
+        assert_eq!(Some(2), FuzzyQuery::new("straße", 2, false).distance("Die Straẞe"));
+    }
+}
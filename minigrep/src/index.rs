@@ -0,0 +1,611 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+
+use caseless::Caseless;
+
+use crate::input::{self, Input};
+use crate::{walk, Config};
+
+
+/// The name of the index file in an indexed directory
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+/// Starts every index file
+const MAGIC: &[u8; 4] = b"MGIX";
+
+/// The version of the index format; an index of another version, or without the magic bytes
+/// of this one, like the JSON of version 2 and older, is rebuilt from scratch
+const INDEX_VERSION: u32 = 3;
+
+/// A trigram index of the files in a directory, to shortlist the files that may match a query
+///
+/// For each file, it keeps the set of three-byte sequences in its lines, after case folding,
+/// along with the file's length and modification time, to tell whether the entry is still current.
+/// If a literal query occurs in a line, all of the query's trigrams occur in that line,
+/// so a file without one of them can't match.
+///
+/// A file can change again within the resolution of its modification time, without its
+/// length or modification time changing. So only entries of files that were last modified
+/// strictly before the index was last updated are trusted; the others are always searched.
+///
+/// On disk, it's inverted: each trigram has the list of the files that have it. See `IndexFile`.
+#[derive(Debug, Default)]
+pub struct Index {
+    /// When the last update started, in nanoseconds since the Unix epoch, by the file system's clock
+    updated: u64,
+    /// By path relative to the indexed directory
+    files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    len: u64,
+    /// Nanoseconds since the Unix epoch
+    modified: u64,
+    /// Binary files aren't indexed, as they'd have too many trigrams; they're always searched.
+    binary: bool,
+    /// Sorted; only loaded to update the index
+    trigrams: Vec<u32>,
+}
+
+/// What `Index::update()` did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub files: usize,
+    /// Files that were new or changed, and read again
+    pub updated: usize,
+    /// Files that are gone, or couldn't be read
+    pub removed: usize,
+}
+
+impl Index {
+    /// Load the index of `dir`, with the trigrams of each file, if it has one of the current version
+    pub fn load(dir: &Path) -> io::Result<Option<Index>> {
+        let Some(file) = IndexFile::read(dir)? else {
+            return Ok(None);
+        };
+
+        let mut entries = file.entries.clone();
+        for record in 0..file.trigrams {
+            let (trigram, ids) = file.record(record)?;
+            for id in ids {
+                let entry = entries.get_mut(id as usize).ok_or_else(corrupt)?;
+                entry.trigrams.push(trigram);
+            }
+        }
+
+        let files = file.names.into_iter().zip(entries).collect();
+        Ok(Some(Index { updated: file.updated, files }))
+    }
+
+    /// Build or update the index of `dir`, and save it there
+    ///
+    /// Only the files that are new, or whose length or modification time changed, are read,
+    /// along with those modified too close to the last update to trust; the entries of files
+    /// that are gone are dropped. `config` decides which files are indexed, as it does which
+    /// are searched. Files that can't be read are reported on stderr.
+    ///
+    /// The index is written to a temp file, which is renamed over the index file.
+    pub fn update(dir: &Path, config: &Config) -> io::Result<Stats> {
+        let old = Index::load(dir)?.unwrap_or_default();
+
+        // The temp file is created before any file is read, so that its modification time
+        // tells, by the same clock as theirs, which files may have changed since.
+        let temp = dir.join(format!("{}.{}.tmp", INDEX_FILE, process::id()));
+        let file = File::create(&temp)?;
+
+        let saved = file.metadata().map(|metadata| modified(&metadata)).and_then(|updated| {
+            let mut index = Index { updated, files: BTreeMap::new() };
+            let stats = index.add_files(dir, config, &old)?;
+            index.write(file)?;
+            fs::rename(&temp, dir.join(INDEX_FILE))?;
+            Ok(stats)
+        });
+        if saved.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        saved
+    }
+
+    /// Add the entries of the files in `dir` to the index, taking those of `old` that are still current
+    fn add_files(&mut self, dir: &Path, config: &Config, old: &Index) -> io::Result<Stats> {
+        let mut stats = Stats::default();
+
+        let config = Config { paths: vec![dir.to_string_lossy().into_owned()], ..config.clone() };
+        let files = walk::files(&config).map_err(io::Error::other)?;
+
+        for path in files.filter_map(|path| path.map_err(|err| eprintln!("minigrep: {}", err)).ok()) {
+            let name = relative_name(dir, &path);
+            let entry = match fs::metadata(&path).and_then(|metadata| Entry::current(old.files.get(&name), old.updated, &path, &metadata)) {
+                Ok((entry, updated)) => {
+                    stats.updated += usize::from(updated);
+                    entry
+                }
+                Err(err) => {
+                    eprintln!("minigrep: {}: {}", input::display_name(&path), err);
+                    continue;
+                }
+            };
+            self.files.insert(name, entry);
+        }
+
+        stats.files = self.files.len();
+        stats.removed = old.files.keys().filter(|name| !self.files.contains_key(*name)).count();
+
+        Ok(stats)
+    }
+
+    /// Write the index in the format of `IndexFile`
+    fn write(&self, file: File) -> io::Result<()> {
+        // The files are numbered in the order of their names, so each posting list comes out sorted.
+        let mut postings: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for (id, entry) in self.files.values().enumerate() {
+            for &trigram in &entry.trigrams {
+                postings.entry(trigram).or_default().push(id as u32);
+            }
+        }
+
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+        writer.write_all(&self.updated.to_le_bytes())?;
+        writer.write_all(&(self.files.len() as u32).to_le_bytes())?;
+        writer.write_all(&(postings.len() as u32).to_le_bytes())?;
+
+        for (name, entry) in &self.files {
+            writer.write_all(&(name.len() as u32).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&entry.len.to_le_bytes())?;
+            writer.write_all(&entry.modified.to_le_bytes())?;
+            writer.write_all(&[u8::from(entry.binary)])?;
+        }
+
+        let mut lists = Vec::new();
+        for (trigram, ids) in &postings {
+            writer.write_all(&trigram.to_le_bytes())?;
+            writer.write_all(&(lists.len() as u32).to_le_bytes())?;
+            writer.write_all(&(ids.len() as u32).to_le_bytes())?;
+
+            let mut last = 0;
+            for &id in ids {
+                write_varint(&mut lists, id - last);
+                last = id;
+            }
+        }
+        writer.write_all(&lists)?;
+
+        writer.flush()
+    }
+}
+
+impl Entry {
+    /// `old`, from an index updated at `updated`, if it's still current for the file at `path`,
+    /// or else a new entry; and whether it's new
+    fn current(old: Option<&Entry>, updated: u64, path: &Path, metadata: &Metadata) -> io::Result<(Entry, bool)> {
+        if let Some(old) = old.filter(|old| old.is_current(metadata, updated)) {
+            return Ok((old.clone(), false));
+        }
+
+        let mut input = Input::open(path, true)?;
+        let binary = input.is_binary()?;
+        let mut trigrams = BTreeSet::new();
+
+        if !binary {
+            let mut folded = String::new();
+            while let Some((_, line)) = input.next_line()? {
+                folded.clear();
+                folded.extend(line.chars().default_case_fold());
+                trigrams.extend(folded.as_bytes().windows(3).map(trigram));
+            }
+        }
+
+        let entry = Entry { len: metadata.len(), modified: modified(metadata), binary, trigrams: trigrams.into_iter().collect() };
+        Ok((entry, true))
+    }
+
+    /// Whether the entry, from an index updated at `updated`, still describes the file with `metadata`
+    ///
+    /// A file modified at or after the update may have changed again after it was read,
+    /// within the same tick of the clock, so its entry is never current.
+    fn is_current(&self, metadata: &Metadata, updated: u64) -> bool {
+        self.is_trusted(updated) && self.len == metadata.len() && self.modified == modified(metadata)
+    }
+
+    /// Whether the file was last modified strictly before an update at `updated`
+    fn is_trusted(&self, updated: u64) -> bool {
+        self.modified < updated
+    }
+}
+
+/// An index file, read into memory, with its file entries parsed
+///
+/// All numbers are little-endian. After `MAGIC`, the version as a `u32`, the update time as
+/// a `u64`, and the number of files and of trigrams as `u32`s, come:
+///
+/// - for each file, in the order of the names, which numbers them from 0: the length of the
+///   name as a `u32`, the name in UTF-8, the length and modification time as `u64`s, and 1 if
+///   the file is binary or else 0;
+/// - for each trigram, in order, a record of three `u32`s: the trigram, the offset of its
+///   posting list after the last record, and the number of files in it;
+/// - the posting lists: the numbers of the files, each as the difference from the one before,
+///   in the LEB128 variable-length encoding.
+///
+/// The records have a fixed size, so a search only decodes the posting lists of its own trigrams.
+struct IndexFile {
+    bytes: Vec<u8>,
+    updated: u64,
+    names: Vec<String>,
+    /// Without their trigrams
+    entries: Vec<Entry>,
+    /// Where the trigram records start in `bytes`
+    records: usize,
+    trigrams: usize,
+}
+
+/// The size of a trigram record
+const RECORD_SIZE: usize = 12;
+
+impl IndexFile {
+    /// Read the index file of `dir`, if it has one of the current version
+    fn read(dir: &Path) -> io::Result<Option<IndexFile>> {
+        let bytes = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut cursor = Cursor(&bytes);
+        if cursor.take(MAGIC.len()).ok() != Some(&MAGIC[..]) || cursor.u32().ok() != Some(INDEX_VERSION) {
+            return Ok(None);
+        }
+        let updated = cursor.u64()?;
+        let files = cursor.u32()? as usize;
+        let trigrams = cursor.u32()? as usize;
+
+        let mut names = Vec::new();
+        let mut entries = Vec::new();
+        for _ in 0..files {
+            let len = cursor.u32()? as usize;
+            let name = String::from_utf8(cursor.take(len)?.to_vec()).map_err(|_| corrupt())?;
+            let (len, modified, binary) = (cursor.u64()?, cursor.u64()?, cursor.u8()? != 0);
+            names.push(name);
+            entries.push(Entry { len, modified, binary, trigrams: Vec::new() });
+        }
+
+        let records = bytes.len() - cursor.0.len();
+        if cursor.0.len() < trigrams * RECORD_SIZE {
+            return Err(corrupt());
+        }
+
+        Ok(Some(IndexFile { bytes, updated, names, entries, records, trigrams }))
+    }
+
+    /// The trigram of the record at `index`, and the numbers of the files that have it
+    fn record(&self, index: usize) -> io::Result<(u32, Vec<u32>)> {
+        let start = self.records + index * RECORD_SIZE;
+        let mut record = Cursor(&self.bytes[start..start + RECORD_SIZE]);
+        let (trigram, offset, len) = (record.u32()?, record.u32()? as usize, record.u32()?);
+
+        let lists = self.records + self.trigrams * RECORD_SIZE;
+        let mut list = Cursor(self.bytes.get(lists + offset..).ok_or_else(corrupt)?);
+        let mut ids = Vec::with_capacity(len as usize);
+        let mut last = 0u32;
+        for _ in 0..len {
+            last = last.checked_add(list.varint()?).ok_or_else(corrupt)?;
+            ids.push(last);
+        }
+
+        Ok((trigram, ids))
+    }
+
+    /// The numbers of the files that have `trigram`, by binary search of the records
+    fn files_with(&self, trigram: u32) -> io::Result<Vec<u32>> {
+        let (mut low, mut high) = (0, self.trigrams);
+        while low < high {
+            let middle = (low + high) / 2;
+            let start = self.records + middle * RECORD_SIZE;
+            let found = Cursor(&self.bytes[start..start + 4]).u32()?;
+            match found.cmp(&trigram) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(self.record(middle)?.1),
+            }
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Which files may match any of `queries`, each a set of trigrams, by file number
+    ///
+    /// Those are the files that have all trigrams of a query, by intersecting their posting lists,
+    /// and the files that are binary, or too recent to trust, which always may.
+    fn candidates(&self, queries: &[Vec<u32>]) -> io::Result<Vec<bool>> {
+        let mut candidates: Vec<bool> = self.entries.iter().map(|entry| entry.binary || !entry.is_trusted(self.updated)).collect();
+
+        for query in queries {
+            let mut lists = query.iter().map(|&trigram| self.files_with(trigram)).collect::<io::Result<Vec<Vec<u32>>>>()?;
+            // Starting from the shortest list keeps the intersection small.
+            lists.sort_by_key(Vec::len);
+            let Some((shortest, rest)) = lists.split_first_mut() else {
+                continue;
+            };
+            for list in rest {
+                shortest.retain(|id| list.binary_search(id).is_ok());
+            }
+
+            for &id in shortest.iter() {
+                *candidates.get_mut(id as usize).ok_or_else(corrupt)? = true;
+            }
+        }
+
+        Ok(candidates)
+    }
+}
+
+/// Reads the fields of an index file one after the other
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(corrupt());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("Expected four bytes.")))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("Expected eight bytes.")))
+    }
+
+    /// A number in LEB128: seven bits at a time, lowest first, with the high bit set on all but the last byte
+    fn varint(&mut self) -> io::Result<u32> {
+        let mut value = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.u8()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(corrupt())
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "the index is corrupt; run `minigrep index` again")
+}
+
+/// The modification time of a file in nanoseconds since the Unix epoch, or 0 if it's unknown
+fn modified(metadata: &Metadata) -> u64 {
+    let since_epoch = metadata.modified().ok().and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+    since_epoch.map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]])
+}
+
+/// The name of the file at `path` in the index of `dir`
+fn relative_name(dir: &Path, path: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).to_string_lossy().into_owned()
+}
+
+/// The indexes of the directories to search, to skip the files that can't match
+pub struct Shortlist {
+    indexes: Vec<(PathBuf, Candidates)>,
+}
+
+/// What an index says about the files of its directory, for one search
+struct Candidates {
+    /// File numbers by name
+    ids: HashMap<String, usize>,
+    entries: Vec<Entry>,
+    /// By file number, whether the file may match
+    may_match: Vec<bool>,
+}
+
+impl Shortlist {
+    /// Load the indexes of the directories in the paths of `config`
+    ///
+    /// Returns `None` if there are none, or if they can't help with this search: for regular
+    /// expressions, fuzzy matching, patterns of fewer than three bytes, or when every file has
+    /// to be searched anyway, as with `-v` or `-c`, or with `--json`, whose summary counts every
+    /// file searched. Indexes that can't be read are reported on stderr.
+    pub fn load(config: &Config) -> Option<Shortlist> {
+        let every_file = config.invert_match || (config.count && !config.files_with_matches) || config.json;
+        if config.regex || config.fuzzy.is_some() || every_file || config.no_decompress || config.no_index {
+            return None;
+        }
+
+        let folded = config.patterns.iter().map(|pattern| pattern.chars().default_case_fold().collect::<String>());
+        let queries: Vec<Vec<u32>> = folded.map(|pattern| pattern.as_bytes().windows(3).map(trigram).collect()).collect();
+        if queries.iter().any(Vec::is_empty) {
+            return None;
+        }
+
+        let dirs = config.paths.iter().map(PathBuf::from).filter(|path| path.is_dir());
+        let indexes: Vec<(PathBuf, Candidates)> = dirs
+            .filter_map(|dir| match Candidates::load(&dir, &queries) {
+                Ok(candidates) => candidates.map(|candidates| (dir, candidates)),
+                Err(err) => {
+                    eprintln!("minigrep: {}: {}", dir.join(INDEX_FILE).display(), err);
+                    None
+                }
+            })
+            .collect();
+
+        (!indexes.is_empty()).then_some(Shortlist { indexes })
+    }
+
+    /// Whether the file at `path` may match; only files in an index that lack the trigrams of all patterns can't
+    ///
+    /// Only those files are looked at, to make sure that they haven't changed since the index was updated.
+    pub fn may_match(&self, path: &Path) -> bool {
+        let Some((dir, candidates)) = self.indexes.iter().find(|(dir, _)| path.starts_with(dir)) else {
+            return true;
+        };
+        let Some(&id) = candidates.ids.get(&relative_name(dir, path)) else {
+            return true;
+        };
+        if candidates.may_match[id] {
+            return true;
+        }
+
+        let entry = &candidates.entries[id];
+        !fs::metadata(path).is_ok_and(|metadata| entry.len == metadata.len() && entry.modified == modified(&metadata))
+    }
+}
+
+impl Candidates {
+    /// Load the index of `dir`, if it has one of the current version, and find the files that may match `queries`
+    fn load(dir: &Path, queries: &[Vec<u32>]) -> io::Result<Option<Candidates>> {
+        let Some(file) = IndexFile::read(dir)? else {
+            return Ok(None);
+        };
+
+        let may_match = file.candidates(queries)?;
+        let ids = file.names.iter().cloned().enumerate().map(|(id, name)| (name, id)).collect();
+        Ok(Some(Candidates { ids, entries: file.entries, may_match }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("minigrep-index-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("birds.txt"), "Is there a bird here?\nPerhaps a Flamingo?\n").unwrap();
+        fs::write(dir.join("sub/swans.txt"), "Or a swan?\n").unwrap();
+        fs::write(dir.join("binary"), "a bird\0").unwrap();
+        for name in ["birds.txt", "sub/swans.txt", "binary"] {
+            set_modified(&dir.join(name), SystemTime::now() - Duration::from_secs(60));
+        }
+        dir
+    }
+
+    /// Files modified as the index is updated aren't trusted, so the files of the tests are older.
+    fn set_modified(path: &Path, time: SystemTime) {
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    fn shortlist(dir: &Path, patterns: &[&str]) -> Vec<String> {
+        let config = Config { patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(), paths: vec![dir.to_string_lossy().into_owned()], ..Config::default() };
+        let shortlist = Shortlist::load(&config).expect("Expected the index to be used.");
+
+        let mut files: Vec<String> = walk::files(&config).unwrap().map(Result::unwrap).filter(|path| shortlist.may_match(path)).map(|path| relative_name(dir, &path)).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn shortlists_files() {
+        let dir = dir("shortlist");
+        assert_eq!(Stats { files: 3, updated: 3, removed: 0 }, Index::update(&dir, &Config::default()).unwrap());
+
+        assert_eq!(vec!["binary", "birds.txt"], shortlist(&dir, &["bird"]));
+        assert_eq!(vec!["binary", "sub/swans.txt"], shortlist(&dir, &["swan"]));
+        assert_eq!(vec!["binary", "birds.txt", "sub/swans.txt"], shortlist(&dir, &["swan", "FLAMINGO"]), "Expected case folding to be indexed.");
+        assert_eq!(vec!["binary"], shortlist(&dir, &["owl"]));
+
+        let config = Config { patterns: vec!["ow".to_string()], paths: vec![dir.to_string_lossy().into_owned()], ..Config::default() };
+        assert!(Shortlist::load(&config).is_none(), "Expected short patterns not to use the index.");
+        assert!(Shortlist::load(&Config { regex: true, patterns: vec!["owl".to_string()], ..config }).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn updates_by_modification_time() {
+        let dir = dir("update");
+        Index::update(&dir, &Config::default()).unwrap();
+        assert_eq!(Stats { files: 3, updated: 0, removed: 0 }, Index::update(&dir, &Config::default()).unwrap());
+
+        fs::write(dir.join("sub/swans.txt"), "Or perhaps an owl?\n").unwrap();
+        assert_eq!(vec!["binary", "sub/swans.txt"], shortlist(&dir, &["owl"]), "Expected a changed file to be searched before it's indexed again.");
+
+        fs::remove_file(dir.join("birds.txt")).unwrap();
+        set_modified(&dir.join("sub/swans.txt"), SystemTime::now() - Duration::from_secs(60));
+        assert_eq!(Stats { files: 2, updated: 1, removed: 1 }, Index::update(&dir, &Config::default()).unwrap());
+        assert_eq!(vec!["binary"], shortlist(&dir, &["swan"]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_modified_during_an_update_are_searched() {
+        let dir = dir("racy");
+        // As if the file had been written in the same tick of the clock as the update
+        let soon = SystemTime::now() + Duration::from_secs(60);
+        set_modified(&dir.join("sub/swans.txt"), soon);
+        Index::update(&dir, &Config::default()).unwrap();
+
+        // Rewritten without changing its length or modification time
+        fs::write(dir.join("sub/swans.txt"), "Or an owl?\n").unwrap();
+        set_modified(&dir.join("sub/swans.txt"), soon);
+        assert_eq!(vec!["binary", "sub/swans.txt"], shortlist(&dir, &["owl"]));
+        assert_eq!(Stats { files: 3, updated: 1, removed: 0 }, Index::update(&dir, &Config::default()).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn old_indexes_are_rebuilt() {
+        let dir = dir("old");
+        fs::write(dir.join(INDEX_FILE), r#"{"version":2,"updated":0,"files":{}}"#).unwrap();
+        assert!(Index::load(&dir).unwrap().is_none());
+        assert_eq!(Stats { files: 3, updated: 3, removed: 0 }, Index::update(&dir, &Config::default()).unwrap());
+
+        let index = Index::load(&dir).unwrap().expect("Expected the rebuilt index to load.");
+        let swans = &index.files["sub/swans.txt"].trigrams;
+        assert!(swans.is_sorted() && swans.contains(&trigram(b"swa")) && !swans.contains(&trigram(b"bir")));
+        assert!(index.files["binary"].binary);
+
+        let bytes = fs::read(dir.join(INDEX_FILE)).unwrap();
+        fs::write(dir.join(INDEX_FILE), &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, Index::load(&dir).unwrap_err().kind());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_searches_every_file() {
+        let dir = dir("json");
+        Index::update(&dir, &Config::default()).unwrap();
+
+        let config = Config { patterns: vec!["bird".to_string()], paths: vec![dir.to_string_lossy().into_owned()], json: true, ..Config::default() };
+        assert!(Shortlist::load(&config).is_none(), "Expected the summary to count every file.");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::decompress;
+
+
+/// The path that stands for standard input
+pub const STDIN_PATH: &str = "-";
+
+/// How `Input` names standard input in the output
+pub const STDIN_NAME: &str = "(standard input)";
+
+/// How much of a file is buffered at a time, and checked for binary content up front
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// A file, or standard input, read line by line
+///
+/// Only one line is held in memory at a time, so input of any size can be searched.
+/// Lines that aren't valid UTF-8 are decoded lossily, with U+FFFD for the invalid bytes.
+pub struct Input<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    line: Vec<u8>,
+    line_number: usize,
+    /// The byte offset of the next line in the input
+    offset: u64,
+}
+
+impl<'a> Input<'a> {
+    /// Open the file at `path`, or standard input if `path` is `-`
+    ///
+    /// With `decompress`, input compressed with gzip, bzip2 or Zstandard is decompressed as it's read.
+    pub fn open(path: &Path, decompress: bool) -> io::Result<Input<'a>> {
+        let reader: Box<dyn BufRead + 'a> = if path == Path::new(STDIN_PATH) {
+            Box::new(BufReader::with_capacity(BUFFER_SIZE, io::stdin()))
+        } else {
+            Box::new(BufReader::with_capacity(BUFFER_SIZE, File::open(path)?))
+        };
+
+        if decompress {
+            return Ok(Input::new(decompress::decompressed(reader, BUFFER_SIZE)?));
+        }
+        Ok(Input::new(reader))
+    }
+
+    pub fn new(reader: Box<dyn BufRead + 'a>) -> Input<'a> {
+        Input { reader, line: Vec::new(), line_number: 0, offset: 0 }
+    }
+
+    /// Check whether the input looks like a binary file
+    ///
+    /// Like grep, this looks for a NUL byte in the first block of the input.
+    /// Nothing is consumed.
+    pub fn is_binary(&mut self) -> io::Result<bool> {
+        Ok(self.reader.fill_buf()?.contains(&0))
+    }
+
+    /// The next line with its 1-based line number, without the line ending
+    ///
+    /// Returns `None` at the end of the input.
+    pub fn next_line(&mut self) -> io::Result<Option<(usize, Cow<'_, str>)>> {
+        self.line.clear();
+        let len = self.reader.read_until(b'\n', &mut self.line)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.offset += len as u64;
+
+        if self.line.ends_with(b"\n") {
+            self.line.pop();
+            if self.line.ends_with(b"\r") {
+                self.line.pop();
+            }
+        }
+
+        self.line_number += 1;
+        Ok(Some((self.line_number, String::from_utf8_lossy(&self.line))))
+    }
+
+    /// The byte offset in the input where the line that `next_line()` returns next starts
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// The name of `path` in the output
+pub fn display_name(path: &Path) -> Cow<'_, str> {
+    if path == Path::new(STDIN_PATH) {
+        Cow::Borrowed(STDIN_NAME)
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(bytes: &[u8]) -> Input<'_> {
+        Input::new(Box::new(bytes))
+    }
+
+    fn lines(mut input: Input) -> Vec<(usize, String)> {
+        let mut lines = Vec::new();
+        while let Some((line_number, line)) = input.next_line().unwrap() {
+            lines.push((line_number, line.into_owned()));
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_without_endings() {
+        let expected = vec![(1, "one".to_string()), (2, "two".to_string()), (3, String::new()), (4, "four".to_string())];
+
+        assert_eq!(expected, lines(input(b"one\ntwo\r\n\nfour")));
+        assert_eq!(expected, lines(input(b"one\ntwo\r\n\nfour\n")));
+        assert!(lines(input(b"")).is_empty());
+    }
+
+    #[test]
+    fn line_offsets() {
+        let mut input = input(b"one\r\ntwo\n\nfour");
+        let mut offsets = vec![input.offset()];
+        while input.next_line().unwrap().is_some() {
+            offsets.push(input.offset());
+        }
+
+        assert_eq!(vec![0, 5, 9, 10, 14], offsets);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(vec![(1, "caf\u{FFFD} bird".to_string())], lines(input(b"caf\xe9 bird\n")));
+    }
+
+    #[test]
+    fn binary_detection() {
+        let mut binary = input(b"\x7fELF\x00\x01bird\n");
+        assert!(binary.is_binary().unwrap());
+        assert_eq!(1, lines(binary).len(), "Expected the check not to consume the input.");
+
+        assert!(!input(b"bird\n").is_binary().unwrap());
+        assert!(!input(b"").is_binary().unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+
+/// An event of `--json` output; each is printed as one line of JSON
+///
+/// Byte offsets of lines count from the start of the file; the offsets of
+/// submatches count from the start of the line, after invalid UTF-8 was replaced.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonEvent<'a> {
+    /// A selected line
+    Match {
+        path: &'a str,
+        line_number: usize,
+        offset: u64,
+        line: &'a str,
+        submatches: Vec<Submatch<'a>>,
+    },
+    /// A line around a selected line, with `-A`, `-B` or `-C`
+    Context {
+        path: &'a str,
+        line_number: usize,
+        line: &'a str,
+    },
+    /// The totals of the whole search, printed last
+    Summary(Summary),
+}
+
+/// Where a match is in its line
+#[derive(Debug, Serialize)]
+pub struct Submatch<'a> {
+    pub text: &'a str,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl<'a> Submatch<'a> {
+    pub fn new(line: &'a str, span: Range<usize>) -> Submatch<'a> {
+        Submatch { text: &line[span.clone()], start: span.start, end: span.end }
+    }
+}
+
+/// The totals of a search
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// The number of files searched; skipped binary files don't count
+    pub files: usize,
+    pub files_with_matches: usize,
+    pub matched_lines: usize,
+    /// The number of submatches in the matched lines
+    pub matches: usize,
+}
+
+impl JsonEvent<'_> {
+    /// Write the event as a line of JSON
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        serde_json::to_writer(&mut *out, self)?;
+        writeln!(out)
+    }
+}
+
+/// Running totals that the files searched on different threads add up to
+#[derive(Debug, Default)]
+pub struct Totals {
+    files: AtomicUsize,
+    files_with_matches: AtomicUsize,
+    matched_lines: AtomicUsize,
+    matches: AtomicUsize,
+}
+
+impl Totals {
+    /// Add the totals of one file
+    pub fn add(&self, file: &Summary) {
+        self.files.fetch_add(file.files, Ordering::Relaxed);
+        self.files_with_matches.fetch_add(file.files_with_matches, Ordering::Relaxed);
+        self.matched_lines.fetch_add(file.matched_lines, Ordering::Relaxed);
+        self.matches.fetch_add(file.matches, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            files: self.files.load(Ordering::Relaxed),
+            files_with_matches: self.files_with_matches.load(Ordering::Relaxed),
+            matched_lines: self.matched_lines.load(Ordering::Relaxed),
+            matches: self.matches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(event: JsonEvent) -> String {
+        let mut out = Vec::new();
+        event.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn match_event() {
+        let line = "a bird, a Bird";
+        let event = JsonEvent::Match {
+            path: "poem.txt",
+            line_number: 3,
+            offset: 42,
+            line,
+            submatches: vec![Submatch::new(line, 2..6), Submatch::new(line, 10..14)],
+        };
+
+        assert_eq!(
+            concat!(
+                r#"{"type":"match","path":"poem.txt","line_number":3,"offset":42,"line":"a bird, a Bird","#,
+                r#""submatches":[{"text":"bird","start":2,"end":6},{"text":"Bird","start":10,"end":14}]}"#,
+                "\n",
+            ),
+            json(event),
+        );
+    }
+
+    #[test]
+    fn summary_event() {
+        let totals = Totals::default();
+        totals.add(&Summary { files: 1, files_with_matches: 1, matched_lines: 2, matches: 3 });
+        totals.add(&Summary { files: 1, ..Summary::default() });
+
+        assert_eq!(
+            "{\"type\":\"summary\",\"files\":2,\"files_with_matches\":1,\"matched_lines\":2,\"matches\":3}\n",
+            json(JsonEvent::Summary(totals.summary())),
+        );
+    }
+}
@@ -1,198 +1,910 @@
-use std::env;
-use std::error::Error;
-use std::fs;
-
-
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
-}
-
-impl Config {
-    pub fn build(mut args: impl Iterator<Item = String> + ExactSizeIterator) -> Result<Config, &'static str> {
-        args.next();  // The first argument is the path of the executable (the name of the program).
-
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string."),
-        };
-
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path string."),
-        };
-
-        let ignore_arg = match args.next() {
-            Some(arg) => arg.to_lowercase(),
-            None => String::from(""),
-        };
-
-        // The env var takes precedence over the CLI argument, as it is checked last.
-        // To have the CLI argument take precedence over the env var, reverse the order of the two checks.
-        // The last value potentially overwrites the first value.
-        let mut ignore_case = false;
-        if ignore_arg == "i" {
-            ignore_case = true;
-        } else if (ignore_arg == String::from("")) || (ignore_arg != "i") {
-            ignore_case = false;
-        }
-        if env::var("IGNORE_CASE").is_ok() {
-            ignore_case = true;
-        }
-
-        Ok(Config { query, file_path, ignore_case })
-    }
-}
-
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = read_file(&config.file_path)?;
-    // println!("With text:\n{}", contents);  // debug output
-
-    let matching_lines = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
-    };
-    // dbg!(&result);
-
-    for line in matching_lines {
-        println!("{}", line);
-    }
-
-    Ok(())
-}
-
-fn read_file(file_path: &str) -> Result<String, Box<dyn Error>> {
-    let contents = fs::read_to_string(file_path)?;
-
-    Ok(contents)
-}
-
-/// The search string, `query`, can occur in multiple lines, and that is why we return a vector of lines.
-/// We can implement this function to count the same line as many times as `query` appears in it,
-/// or to count the line only once.
-/// The current implementation counts such a line only once.
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
-}
-
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = &query.to_lowercase();
-
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(query))
-        .collect()
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn not_matching() {
-        let query = "Bird";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?";
-
-        let expected: Vec<&str> = vec![];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 0);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn one_line_matches() {
-        let query = "bird";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?";
-
-        let expected = vec!["Is there a bird here?"];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 1);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn one_line_matches_twice() {
-        let query = "bird";
-        let contents = "\
-Is there a bird here, any bird here?
-Perhaps a flamingo?
-Or a swan?";
-
-        let expected = vec!["Is there a bird here, any bird here?"];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 1);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn one_line_matches_with_punctuation() {
-        let query = "bird";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?
-Or any other bird?";
-
-        let expected = vec!["Is there a bird here?", "Or any other bird?"];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 2);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn two_lines_match() {
-        let query = "bird";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?
-Or any other bird here?";
-
-        let expected = vec!["Is there a bird here?", "Or any other bird here?"];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 2);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn two_lines_match_with_punctuation() {
-        let query = "here";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?
-Or any other bird here?";
-
-        let expected = vec!["Is there a bird here?", "Or any other bird here?"];
-        let actual = search(query, contents);
-        assert_eq!(actual.len(), 2);
-        assert_eq!(expected, actual);
-    }
-
-    #[test]
-    fn three_lines_match_case_insensitive() {
-        let query = "birD";
-        let contents = "\
-Is there a bird here?
-Perhaps a flamingo?
-Or a swan?
-Or any other bird here?
-Perhaps Mr. Bird?";
-
-        let expected = vec!["Is there a bird here?", "Or any other bird here?", "Perhaps Mr. Bird?"];
-        let actual = search_case_insensitive(query, contents);
-        assert_eq!(actual.len(), 3);
-        assert_eq!(expected, actual);
-    }
-}
+mod color;
+mod config;
+mod context;
+mod decompress;
+mod fold;
+mod fuzzy;
+mod index;
+mod input;
+mod json;
+mod pool;
+mod printer;
+mod replace;
+mod searcher;
+mod walk;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+
+use index::{Index, Shortlist};
+use json::{JsonEvent, Totals};
+use pool::ThreadPool;
+use printer::{JsonPrinter, StandardPrinter};
+
+pub use color::{Painted, Theme, COLORS_ENV};
+pub use config::{ArgsError, ColorChoice, Config, IGNORE_CASE_ENV, USAGE, VERSION};
+pub use context::Line;
+pub use fold::{FoldedQuery, FoldedSet};
+pub use fuzzy::FuzzyQuery;
+pub use input::Input;
+pub use searcher::{Match, Matches, SearchError, Searcher, Sink};
+
+/// Decides which lines match, and where
+pub struct Matcher {
+    pattern: Pattern,
+    /// Only accept matches that aren't part of a longer word
+    whole_word: bool,
+}
+
+enum Pattern {
+    /// A case-sensitive literal query
+    Literal(String),
+    /// A case-insensitive literal query
+    Folded(FoldedQuery),
+    /// Any number of case-sensitive literal queries but one
+    Literals(AhoCorasick),
+    /// Several case-insensitive literal queries
+    FoldedSet(FoldedSet),
+    /// Literal queries that may be matched with a few typos
+    Fuzzy(Vec<FuzzyQuery>),
+    /// A regular expression, or several as one
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Build the matcher for the patterns of `config`
+    ///
+    /// A line matches if any of the patterns matches; where several match, the match that
+    /// starts first, and of those the longest, wins. Without patterns, nothing matches.
+    /// Many literal patterns are searched for all at once with an Aho-Corasick automaton.
+    ///
+    /// Fails if a pattern is an invalid regular expression, or if there are too many patterns.
+    pub fn build(config: &Config) -> Result<Matcher, PatternError> {
+        let whole_word = config.whole_word;
+        let patterns = &config.patterns;
+
+        let ignore_case = if config.smart_case {
+            !patterns.iter().any(|pattern| fold::has_uppercase(pattern, config.regex))
+        } else {
+            config.ignore_case
+        };
+
+        if let Some(max_distance) = config.fuzzy {
+            let queries = patterns.iter().map(|pattern| FuzzyQuery::new(pattern, max_distance, ignore_case)).collect();
+            return Ok(Matcher { pattern: Pattern::Fuzzy(queries), whole_word });
+        }
+
+        let pattern = match (patterns.as_slice(), config.regex, ignore_case) {
+            ([pattern], false, false) => Pattern::Literal(pattern.clone()),
+            ([pattern], false, true) => Pattern::Folded(FoldedQuery::new(pattern)),
+            // An automaton without patterns never matches, which a regex can't express as easily.
+            ([], _, _) | (_, false, false) => {
+                Pattern::Literals(AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(patterns)?)
+            }
+            (_, false, true) => Pattern::FoldedSet(FoldedSet::new(patterns)?),
+            (_, true, _) => Pattern::Regex(build_regex(patterns, ignore_case)?),
+        };
+
+        Ok(Matcher { pattern, whole_word })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        if self.whole_word {
+            return !self.find_spans(line).is_empty();
+        }
+
+        match &self.pattern {
+            Pattern::Literal(query) => line.contains(query.as_str()),
+            Pattern::Folded(query) => query.find_at(line, 0).is_some(),
+            Pattern::Literals(automaton) => automaton.is_match(line),
+            Pattern::FoldedSet(queries) => queries.find_at(line, 0).is_some(),
+            Pattern::Fuzzy(queries) => queries.iter().any(|query| query.distance(line).is_some()),
+            Pattern::Regex(regex) => regex.is_match(line),
+        }
+    }
+
+    /// How many edits the best match in `line` is away from the query, if `line` matches
+    ///
+    /// Only fuzzy matching allows edits; otherwise, a matching line is 0 edits away.
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        match &self.pattern {
+            Pattern::Fuzzy(queries) if !self.whole_word => queries.iter().filter_map(|query| query.distance(line)).min(),
+            Pattern::Fuzzy(queries) => {
+                let spans = self.find_spans(line);
+                let distance = |span: &Range<usize>| queries.iter().filter_map(|query| query.find_at(line, span.start)).map(|(distance, _)| distance).min();
+                spans.iter().filter_map(distance).min()
+            }
+            _ => self.is_match(line).then_some(0),
+        }
+    }
+
+    /// The byte ranges of all non-overlapping, non-empty matches in `line`
+    pub fn find_spans(&self, line: &str) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+
+        while let Some(span) = self.find_at(line, start) {
+            if span.is_empty() || (self.whole_word && !is_whole_word(line, &span)) {
+                // Look for another match that starts later.
+                match line[span.start..].chars().next() {
+                    Some(c) => start = span.start + c.len_utf8(),
+                    None => break,
+                }
+            } else {
+                start = span.end;
+                spans.push(span);
+            }
+        }
+
+        spans
+    }
+
+    /// `line` with each match replaced by `replacement`
+    ///
+    /// For a regular expression, `$1`, `$name` or `${name}` in `replacement` stand for what
+    /// the capture group matched, and `$$` for a `$`. Otherwise, `replacement` is used as is.
+    pub fn replace<'a>(&self, line: &'a str, replacement: &str) -> Cow<'a, str> {
+        let spans = self.find_spans(line);
+        if spans.is_empty() {
+            return Cow::Borrowed(line);
+        }
+
+        let mut replaced = String::with_capacity(line.len());
+        let mut last = 0;
+
+        for span in spans {
+            replaced.push_str(&line[last..span.start]);
+            match &self.pattern {
+                Pattern::Regex(regex) => {
+                    // The leftmost match from the start of the span is the match of the span.
+                    let captures = regex.captures_at(line, span.start).expect("Expected the match to have captures.");
+                    captures.expand(replacement, &mut replaced);
+                }
+                _ => replaced.push_str(replacement),
+            }
+            last = span.end;
+        }
+        replaced.push_str(&line[last..]);
+
+        Cow::Owned(replaced)
+    }
+
+    /// The first match in `line` that starts at or after the byte offset `start`
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match &self.pattern {
+            Pattern::Literal(query) => line[start..].find(query.as_str()).map(|i| start + i..start + i + query.len()),
+            Pattern::Folded(query) => query.find_at(line, start),
+            Pattern::Literals(automaton) => automaton.find(aho_corasick::Input::new(line).span(start..line.len())).map(|m| m.range()),
+            Pattern::FoldedSet(queries) => queries.find_at(line, start),
+            Pattern::Fuzzy(queries) => {
+                // The match that starts first, and of those the closest.
+                let found = queries.iter().filter_map(|query| query.find_at(line, start));
+                found.min_by_key(|(distance, span)| (span.start, *distance)).map(|(_, span)| span)
+            }
+            Pattern::Regex(regex) => regex.find_at(line, start).map(|m| m.range()),
+        }
+    }
+}
+
+/// Check that the text around `span` doesn't continue a word
+fn is_whole_word(line: &str, span: &Range<usize>) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    !line[..span.start].chars().next_back().is_some_and(is_word_char)
+        && !line[span.end..].chars().next().is_some_and(is_word_char)
+}
+
+/// Build one regular expression that matches where any of `patterns` matches
+fn build_regex(patterns: &[String], ignore_case: bool) -> Result<Regex, PatternError> {
+    // The regex crate folds case one character at a time, so `ß` doesn't match `ss` here.
+    let build = |pattern: &str| RegexBuilder::new(pattern).case_insensitive(ignore_case).build();
+
+    let combined = match patterns {
+        [pattern] => pattern.clone(),
+        _ => patterns.iter().map(|pattern| format!("(?:{pattern})")).collect::<Vec<_>>().join("|"),
+    };
+
+    build(&combined).map_err(|error| {
+        // Blame the pattern that is wrong on its own, if there is one.
+        let invalid = patterns.iter().find_map(|pattern| build(pattern).err().map(|error| (pattern.clone(), error)));
+        let (pattern, error) = invalid.unwrap_or((combined, error));
+        PatternError::Regex { pattern, error }
+    })
+}
+
+/// Patterns that can't be searched for
+#[derive(Debug)]
+pub enum PatternError {
+    /// A pattern isn't a valid regular expression
+    Regex { pattern: String, error: regex::Error },
+    /// There are too many literal patterns for the automaton
+    TooMany(aho_corasick::BuildError),
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Regex { pattern, error } => write!(f, "Invalid regular expression '{}':\n{}", pattern, error),
+            PatternError::TooMany(error) => write!(f, "Too many patterns: {}", error),
+        }
+    }
+}
+
+impl From<aho_corasick::BuildError> for PatternError {
+    fn from(error: aho_corasick::BuildError) -> Self {
+        PatternError::TooMany(error)
+    }
+}
+
+impl Error for PatternError {}
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    if config.index {
+        return update_indexes(&config);
+    }
+
+    let searcher = Searcher::build(&config)?;
+
+    // By default, only highlight when a person is looking; escape codes would garble piped output.
+    let color = !config.json && match config.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => io::stdout().is_terminal(),
+    };
+
+    let with_filename = config
+        .with_filename
+        .unwrap_or_else(|| config.paths.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir()));
+
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    // A single file gains nothing from a pool; stream it straight to the output instead of buffering it.
+    let single_file = config.paths.len() == 1 && !Path::new(&config.paths[0]).is_dir();
+
+    let search = Arc::new(Search { config, searcher, color, with_filename, totals: Totals::default() });
+
+    // Files that an index rules out aren't even opened.
+    let shortlist = Shortlist::load(&search.config);
+
+    // A file that can't be found is reported, and the search goes on with the other files.
+    let files = walk::files(&search.config)?
+        .filter_map(|path| path.map_err(|err| eprintln!("minigrep: {}", err)).ok())
+        .filter(|path| shortlist.as_ref().is_none_or(|shortlist| shortlist.may_match(path)));
+
+    if threads == 1 || single_file {
+        let mut out = io::stdout().lock();
+        for path in files {
+            search.path(&mut out, &path)?;
+        }
+    } else {
+        search_in_parallel(&search, files, threads)?;
+    }
+
+    if search.config.json {
+        JsonEvent::Summary(search.totals.summary()).write(&mut io::stdout().lock())?;
+    }
+
+    Ok(())
+}
+
+/// Build or update the index of each directory in the paths of `config`, and print what changed
+fn update_indexes(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+
+    for dir in &config.paths {
+        if !Path::new(dir).is_dir() {
+            return Err(format!("{}: Not a directory", dir).into());
+        }
+
+        let stats = Index::update(Path::new(dir), config)?;
+        writeln!(out, "{}: {} files indexed, {} updated, {} removed", dir, stats.files, stats.updated, stats.removed)?;
+    }
+
+    Ok(())
+}
+
+/// How many files may be searched, or waiting to be printed, at a time for each thread
+const FILES_IN_FLIGHT_PER_THREAD: usize = 4;
+
+/// Search `files` on a pool of `threads` workers
+///
+/// Each file's output is buffered, and printed in one piece once the file is done,
+/// in the order in which the files finish, or with `--sort`, in the order of `files`.
+/// Only so many files are in flight at a time, so a slow file holds up a bounded number
+/// of buffered outputs behind it. Once the output is closed, the search stops.
+fn search_in_parallel(search: &Arc<Search>, files: impl Iterator<Item = PathBuf>, threads: usize) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel::<(usize, Vec<u8>)>();
+    // A file takes a slot before it's queued, and the printer frees it once the file's output is printed.
+    let (slots, freed) = mpsc::sync_channel::<()>(threads * FILES_IN_FLIGHT_PER_THREAD);
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let sort = search.config.sort;
+    let printer = {
+        let stopped = Arc::clone(&stopped);
+        thread::spawn(move || {
+            let printed = print_outputs(receiver, freed, sort);
+            stopped.store(true, Ordering::Relaxed);
+            printed
+        })
+    };
+
+    let pool = ThreadPool::new(threads);
+    for (index, path) in files.enumerate() {
+        // Taking a slot fails once the printer is gone.
+        if stopped.load(Ordering::Relaxed) || slots.send(()).is_err() {
+            break;
+        }
+        let search = Arc::clone(search);
+        let sender = sender.clone();
+        let stopped = Arc::clone(&stopped);
+
+        pool.execute(move || {
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut output = Vec::new();
+                search.path(&mut output, &path).expect("Expected writing to memory to succeed.");
+                output
+            }));
+            // A file whose search panicked still has to send something, or the files after it wait forever with `--sort`.
+            let output = searched.unwrap_or_else(|_| {
+                eprintln!("minigrep: {}: the search failed", input::display_name(&path));
+                Vec::new()
+            });
+            // The printer only stops listening when it can't write anymore; then the output has nowhere to go.
+            if sender.send((index, output)).is_err() {
+                stopped.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    // The printer is done once the last job has sent its output.
+    drop(sender);
+    drop(pool);
+
+    printer.join().expect("Expected to join the printer thread.")
+}
+
+/// Print each file's output as it arrives, or with `sort`, in the order of the indexes
+///
+/// Each output that is printed frees a slot in `freed`.
+fn print_outputs(receiver: mpsc::Receiver<(usize, Vec<u8>)>, freed: mpsc::Receiver<()>, sort: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+
+    for (index, output) in receiver {
+        if !sort {
+            out.write_all(&output)?;
+            let _ = freed.try_recv();
+            continue;
+        }
+
+        pending.insert(index, output);
+        while let Some(output) = pending.remove(&next) {
+            out.write_all(&output)?;
+            let _ = freed.try_recv();
+            next += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// What is needed to search a file, shared by the workers
+struct Search {
+    config: Config,
+    searcher: Searcher,
+    color: bool,
+    with_filename: bool,
+    /// What all files searched so far add up to, for `--json`
+    totals: Totals,
+}
+
+impl Search {
+    /// Search the file at `path`, or standard input for `-`, and print what the config asks for to `out`
+    ///
+    /// A file that can't be opened is reported on stderr; errors while writing are returned.
+    fn path(&self, out: &mut impl Write, path: &Path) -> io::Result<()> {
+        let name = input::display_name(path);
+
+        if let (Some(replacement), true) = (&self.config.replace, self.config.in_place) {
+            return self.rewrite(out, path, &name, replacement);
+        }
+
+        match Input::open(path, !self.config.no_decompress) {
+            Ok(input) => self.input(out, input, &name),
+            Err(err) => {
+                eprintln!("minigrep: {}: {}", name, err);
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace the matches in the file at `path`, or with `--dry-run`, print what would change to `out`
+    ///
+    /// Files that can't be rewritten are reported on stderr; errors while writing to `out` are returned.
+    fn rewrite(&self, out: &mut impl Write, path: &Path, name: &str, replacement: &str) -> io::Result<()> {
+        if path == Path::new(input::STDIN_PATH) {
+            eprintln!("minigrep: {}: can't be replaced in place", name);
+            return Ok(());
+        }
+
+        let dry_run = self.config.dry_run;
+        let mut preview = Vec::new();
+
+        let rewritten = replace::rewrite_file(path, self.searcher.matcher(), replacement, replace::Options::new(&self.config), |line_number, old, new| {
+            if dry_run {
+                // Writing to memory can't fail.
+                let _ = writeln!(preview, "{}:{}:-{}\n{}:{}:+{}", name, line_number, old, name, line_number, new);
+            }
+        });
+        if let Err(err) = rewritten {
+            eprintln!("minigrep: {}: {}", name, err);
+        }
+
+        out.write_all(&preview)
+    }
+
+    /// Search `input` line by line, and print what the config asks for to `out`
+    ///
+    /// Errors while reading are reported on stderr, and end the search of this input;
+    /// errors while writing are returned.
+    fn input(&self, out: &mut impl Write, input: Input, name: &str) -> io::Result<()> {
+        let searched = if self.config.json {
+            self.searcher.search(input, &mut JsonPrinter::new(out, name, &self.totals))
+        } else {
+            let mut printer = StandardPrinter::new(out, name, &self.config).color(self.color).with_filename(self.with_filename);
+            self.searcher.search(input, &mut printer)
+        };
+
+        match searched {
+            Ok(()) => Ok(()),
+            Err(SearchError::Read(err)) => {
+                eprintln!("minigrep: {}: {}", name, err);
+                Ok(())
+            }
+            Err(SearchError::Sink(err)) => Err(err),
+        }
+    }
+}
+
+/// The search string, `query`, can occur in multiple lines, and that is why we return a vector of lines.
+/// We can implement this function to count the same line as many times as `query` appears in it,
+/// or to count the line only once.
+/// The current implementation counts such a line only once.
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| line.contains(query))
+        .collect()
+}
+
+/// Like `search()`, but regardless of case, with full Unicode case folding; see `FoldedQuery`
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let query = FoldedQuery::new(query);
+
+    contents
+        .lines()
+        .filter(|line| query.find_at(line, 0).is_some())
+        .collect()
+}
+
+/// The lines with a part that is at most `max_distance` edits away from `query`, closest first
+///
+/// Each line comes with its distance; see `FuzzyQuery`. Lines at the same distance keep their order.
+pub fn search_fuzzy<'a>(query: &str, contents: &'a str, max_distance: usize) -> Vec<(usize, &'a str)> {
+    let query = FuzzyQuery::new(query, max_distance, false);
+
+    let mut found: Vec<(usize, &str)> = contents
+        .lines()
+        .filter_map(|line| query.distance(line).map(|distance| (distance, line)))
+        .collect();
+    found.sort_by_key(|&(distance, _)| distance);
+
+    found
+}
+
+/// Search with any `Matcher`; `search()` and `search_case_insensitive()` are its literal special cases.
+#[cfg(test)]
+fn search_with<'a>(matcher: &Matcher, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| matcher.is_match(line))
+        .collect()
+}
+
+/// Select the lines that match, or with `invert`, those that don't, with their 1-based line numbers
+#[cfg(test)]
+fn select_lines<'a>(matcher: &Matcher, contents: &'a str, invert: bool) -> Vec<(usize, &'a str)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| matcher.is_match(line) != invert)
+        .map(|(i, line)| (i + 1, line))
+        .collect()
+}
+
+/// Wrap the byte ranges `spans` of `line` in escape codes that highlight them in a terminal, in bold red
+///
+/// `spans` must be sorted and must not overlap. See `Theme::highlight()` for other colors.
+#[cfg(test)]
+fn highlight(line: &str, spans: &[Range<usize>]) -> String {
+    Theme::default().highlight(line, spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_matching() {
+        let query = "Bird";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?";
+
+        let expected: Vec<&str> = vec![];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 0);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn one_line_matches() {
+        let query = "bird";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?";
+
+        let expected = vec!["Is there a bird here?"];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 1);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn one_line_matches_twice() {
+        let query = "bird";
+        let contents = "\
+Is there a bird here, any bird here?
+Perhaps a flamingo?
+Or a swan?";
+
+        let expected = vec!["Is there a bird here, any bird here?"];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 1);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn one_line_matches_with_punctuation() {
+        let query = "bird";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?
+Or any other bird?";
+
+        let expected = vec!["Is there a bird here?", "Or any other bird?"];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 2);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn two_lines_match() {
+        let query = "bird";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?
+Or any other bird here?";
+
+        let expected = vec!["Is there a bird here?", "Or any other bird here?"];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 2);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn two_lines_match_with_punctuation() {
+        let query = "here";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?
+Or any other bird here?";
+
+        let expected = vec!["Is there a bird here?", "Or any other bird here?"];
+        let actual = search(query, contents);
+        assert_eq!(actual.len(), 2);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn three_lines_match_case_insensitive() {
+        let query = "birD";
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?
+Or any other bird here?
+Perhaps Mr. Bird?";
+
+        let expected = vec!["Is there a bird here?", "Or any other bird here?", "Perhaps Mr. Bird?"];
+        let actual = search_case_insensitive(query, contents);
+        assert_eq!(actual.len(), 3);
+        assert_eq!(expected, actual);
+    }
+
+    fn config(query: &str, ignore_case: bool, regex: bool) -> Config {
+        Config { patterns: vec![query.to_string()], ignore_case, regex, ..Config::default() }
+    }
+
+    #[test]
+    fn regex_matches() {
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?
+Or any other Bird here?";
+
+        let matcher = Matcher::build(&config(r"^(Or|Is) .*\bbird\b", false, true)).unwrap();
+        assert_eq!(vec!["Is there a bird here?"], search_with(&matcher, contents));
+
+        let matcher = Matcher::build(&config(r"^(Or|Is) .*\bbird\b", true, true)).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Or any other Bird here?"], search_with(&matcher, contents));
+    }
+
+    #[test]
+    fn literal_query_is_not_a_regex() {
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo.";
+
+        let matcher = Matcher::build(&config("here?", true, false)).unwrap();
+        assert_eq!(vec!["Is there a bird here?"], search_with(&matcher, contents));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let error = Matcher::build(&config("bird(s", false, true)).err().unwrap();
+        let message = error.to_string();
+
+        assert!(message.starts_with("Invalid regular expression 'bird(s':"));
+        assert!(message.contains("unclosed group"));
+    }
+
+    #[test]
+    fn spans_are_highlighted() {
+        let line = "A bird, a Bird, a BIRD.";
+
+        let matcher = Matcher::build(&config("bird", true, false)).unwrap();
+        assert_eq!(vec![2..6, 10..14, 18..22], matcher.find_spans(line));
+        assert_eq!(
+            "A \x1b[1;31mbird\x1b[0m, a \x1b[1;31mBird\x1b[0m, a \x1b[1;31mBIRD\x1b[0m.",
+            highlight(line, &matcher.find_spans(line))
+        );
+
+        let matcher = Matcher::build(&config("x*", false, true)).unwrap();
+        assert!(matcher.find_spans(line).is_empty());
+    }
+
+    #[test]
+    fn whole_words() {
+        let contents = "\
+Is there a bird here?
+Are there birds or a songbird?
+A bird_feeder, a bird-feeder.";
+
+        let matcher = Matcher::build(&Config { whole_word: true, ..config("bird", false, false) }).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "A bird_feeder, a bird-feeder."], search_with(&matcher, contents));
+        assert_eq!(vec![17..21], matcher.find_spans("A bird_feeder, a bird-feeder."));
+
+        let matcher = Matcher::build(&Config { whole_word: true, ..config(r"b\w+s", false, true) }).unwrap();
+        assert_eq!(vec!["Are there birds or a songbird?"], search_with(&matcher, contents));
+    }
+
+    #[test]
+    fn inverted_lines_with_numbers() {
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a swan?";
+
+        let matcher = Matcher::build(&config("bird", false, false)).unwrap();
+        assert_eq!(vec![(1, "Is there a bird here?")], select_lines(&matcher, contents, false));
+        assert_eq!(vec![(2, "Perhaps a flamingo?"), (3, "Or a swan?")], select_lines(&matcher, contents, true));
+    }
+
+    #[test]
+    fn unicode_case_folding() {
+        let contents = "\
+Die Straße ist lang.
+DIE STRASSE IST LANG.
+İstanbul";
+
+        assert_eq!(vec!["Die Straße ist lang.", "DIE STRASSE IST LANG."], search_case_insensitive("strasse", contents));
+        assert_eq!(vec!["İstanbul"], search_case_insensitive("i\u{307}stanbul", contents));
+
+        let matcher = Matcher::build(&config("STRASSE", true, false)).unwrap();
+        assert_eq!(vec![4..11], matcher.find_spans("Die Straße ist lang."));
+    }
+
+    #[test]
+    fn smart_case() {
+        let contents = "\
+Is there a bird here?
+Perhaps Mr. Bird?";
+
+        let matcher = Matcher::build(&Config { smart_case: true, ..config("bird", false, false) }).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Perhaps Mr. Bird?"], search_with(&matcher, contents));
+
+        let matcher = Matcher::build(&Config { smart_case: true, ..config("Bird", true, false) }).unwrap();
+        assert_eq!(vec!["Perhaps Mr. Bird?"], search_with(&matcher, contents));
+
+        // `\W` is an escape, not an uppercase letter.
+        let matcher = Matcher::build(&Config { smart_case: true, ..config(r"\Wbird", false, true) }).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Perhaps Mr. Bird?"], search_with(&matcher, contents));
+    }
+
+    fn search_for(config: Config, with_filename: bool) -> Search {
+        let searcher = Searcher::build(&config).unwrap();
+        Search { config, searcher, color: false, with_filename, totals: Totals::default() }
+    }
+
+    #[test]
+    fn json_output() {
+        let search = search_for(Config { json: true, after_context: 1, ..config("bird", false, false) }, false);
+        let mut out = Vec::new();
+        search.input(&mut out, Input::new(Box::new(&b"Is there a bird, a bird?\nPerhaps a flamingo?\nOr a swan?\n"[..])), "poem.txt").unwrap();
+
+        let events: Vec<serde_json::Value> = out.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+        assert_eq!(2, events.len());
+        assert_eq!("match", events[0]["type"]);
+        assert_eq!(0, events[0]["offset"]);
+        assert_eq!(serde_json::json!([{"text": "bird", "start": 11, "end": 15}, {"text": "bird", "start": 19, "end": 23}]), events[0]["submatches"]);
+        assert_eq!("context", events[1]["type"]);
+        assert_eq!(2, events[1]["line_number"]);
+
+        assert_eq!(json::Summary { files: 1, files_with_matches: 1, matched_lines: 1, matches: 2 }, search.totals.summary());
+    }
+
+    #[test]
+    fn replacements() {
+        let line = "A bird, a Bird, a birdhouse.";
+
+        assert_eq!("A swan, a Bird, a swanhouse.", Matcher::build(&config("bird", false, false)).unwrap().replace(line, "swan"));
+        assert_eq!("A $1, a $1, a $1house.", Matcher::build(&config("bird", true, false)).unwrap().replace(line, "$1"));
+
+        let matcher = Matcher::build(&Config { whole_word: true, ..config(r"(?<first>\w)(\w+)", false, true) }).unwrap();
+        assert_eq!("A bird, a Bird, a birdhouse.", matcher.replace(line, "${first}$2"));
+        assert_eq!("A irdb$, a irdB$, a irdhouseb$.", matcher.replace(line, "${2}${first}$$"));
+
+        let matcher = Matcher::build(&Config { whole_word: true, ..config("(b)ird", false, true) }).unwrap();
+        assert_eq!("A bee, a Bird, a birdhouse.", matcher.replace(line, "${1}ee"));
+
+        assert!(matches!(Matcher::build(&config("swan", false, false)).unwrap().replace(line, "owl"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn several_patterns() {
+        let contents = "\
+Is there a bird here?
+Perhaps a flamingo?
+Or a Swan?";
+        let patterns = |patterns: &[&str], ignore_case: bool, regex: bool| {
+            let config = Config { patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(), ..config("", ignore_case, regex) };
+            Matcher::build(&config)
+        };
+
+        let matcher = patterns(&["bird", "swan", "flamingo"], false, false).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Perhaps a flamingo?"], search_with(&matcher, contents));
+
+        let matcher = patterns(&["bird", "swan", "SWAN"], true, false).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Or a Swan?"], search_with(&matcher, contents));
+        assert_eq!(vec![5..9], matcher.find_spans("Or a Swan?"));
+
+        let matcher = patterns(&["b.rd", "fl[a-z]+"], false, true).unwrap();
+        assert_eq!(vec!["Is there a bird here?", "Perhaps a flamingo?"], search_with(&matcher, contents));
+
+        let matcher = patterns(&["here", "there"], false, false).unwrap();
+        assert_eq!(vec![3..8, 16..20], matcher.find_spans("Is there a bird here?"), "Expected the longest of matches that start together.");
+
+        assert!(search_with(&patterns(&[], false, false).unwrap(), contents).is_empty());
+        assert!(search_with(&patterns(&[], true, true).unwrap(), contents).is_empty());
+
+        let message = patterns(&["bird", "swan(", "owl"], false, true).err().unwrap().to_string();
+        assert!(message.starts_with("Invalid regular expression 'swan(':"));
+    }
+
+    #[test]
+    fn fuzzy_matches_are_ranked() {
+        let contents = "\
+Is there a bord here?
+Perhaps a flamingo?
+Or a bird?
+A brd, a birb?";
+
+        assert_eq!(vec![(0, "Or a bird?"), (1, "Is there a bord here?"), (1, "A brd, a birb?")], search_fuzzy("bird", contents, 1));
+        assert_eq!(vec![(0, "Or a bird?")], search_fuzzy("bird", contents, 0));
+
+        let matcher = Matcher::build(&Config { fuzzy: Some(1), ..config("bird", false, false) }).unwrap();
+        assert_eq!(Some(1), matcher.distance("A brd, a birb?"));
+        assert_eq!(vec![2..5, 9..13], matcher.find_spans("A brd, a birb?"));
+        assert_eq!(None, matcher.distance("Perhaps a flamingo?"));
+
+        let ranked = |config: Config| {
+            let search = search_for(config, false);
+            let mut out = Vec::new();
+            search.input(&mut out, Input::new(Box::new(contents.as_bytes())), "poem.txt").unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let config = Config { fuzzy: Some(1), line_number: true, ..config("bird", false, false) };
+        assert_eq!("3:Or a bird?\n1:Is there a bord here?\n4:A brd, a birb?\n", ranked(config.clone()));
+        assert_eq!("3\n", ranked(Config { count: true, ..config }));
+    }
+
+    #[test]
+    fn streamed_output() {
+        let search = |config: &Config, bytes: &'static [u8]| {
+            let mut out = Vec::new();
+            let search = search_for(config.clone(), true);
+            search.input(&mut out, Input::new(Box::new(bytes)), "poem.txt").unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let contents = b"Is there a bird here?\nPerhaps a flamingo?\r\nOr a bird\xff?";
+
+        let config = Config { line_number: true, ..config("bird", false, false) };
+        assert_eq!("poem.txt:1:Is there a bird here?\npoem.txt:3:Or a bird\u{FFFD}?\n", search(&config, contents));
+        assert_eq!("poem.txt:2\n", search(&Config { count: true, ..config.clone() }, contents));
+        assert_eq!("poem.txt\n", search(&Config { files_with_matches: true, ..config.clone() }, contents));
+
+        let config_with_context = Config { after_context: 1, ..config.clone() };
+        assert_eq!(
+            "poem.txt:1:Is there a bird here?\npoem.txt-2-Perhaps a flamingo?\npoem.txt:3:Or a bird\u{FFFD}?\n",
+            search(&config_with_context, contents),
+        );
+
+        let config_with_replace = Config { replace: Some("swan".to_string()), ..config.clone() };
+        assert_eq!("poem.txt:1:Is there a swan here?\npoem.txt:3:Or a swan\u{FFFD}?\n", search(&config_with_replace, contents));
+
+        let config_with_null = Config { null: true, ..config.clone() };
+        assert_eq!("poem.txt\x001:Is there a bird here?\npoem.txt\x003:Or a bird\u{FFFD}?\n", search(&config_with_null, contents));
+        assert_eq!("poem.txt\0", search(&Config { files_with_matches: true, ..config_with_null }, contents));
+
+        let colored = |config: &Config| {
+            let mut out = Vec::new();
+            let search = Search { color: true, ..search_for(config.clone(), true) };
+            search.input(&mut out, Input::new(Box::new(&b"a bird\nno\n"[..])), "poem.txt").unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0ma \x1b[1;31mbird\x1b[0m\n",
+            colored(&config),
+        );
+        let theme = Theme::parse("mt=4:fn=:se=").unwrap();
+        assert_eq!("poem.txt:\x1b[32m1\x1b[0m:a \x1b[4mbird\x1b[0m\n", colored(&Config { theme, ..config.clone() }));
+
+        assert_eq!("", search(&config, b"a bird\0"));
+        assert_eq!("poem.txt:1:a bird\0\n", search(&Config { text: true, ..config.clone() }, b"a bird\0"));
+    }
+}
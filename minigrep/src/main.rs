@@ -1,20 +1,35 @@
 use std::env;
+use std::io;
 use std::process;
 
-use minigrep::Config;
+use minigrep::{ArgsError, Config};
 
 
 fn main() {
     let args = env::args();  // The env::args function returns an iterator whose items are strings.
 
     let config = Config::build(args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(-1);
+        match err {
+            ArgsError::Help | ArgsError::Version => {
+                println!("{}", err);
+                process::exit(0);
+            }
+            ArgsError::Usage(_) => {
+                eprintln!("Problem parsing arguments: {}", err);
+                eprintln!("Try 'minigrep --help' for more information.");
+                process::exit(-1);
+            }
+        }
     });
 
     // println!("Searching for '{}' in file '{}'.", config.query, config.file_path);  // debug output
 
     if let Err(err) = minigrep::run(config) {
+        // Whoever reads the output stopped reading, as `head` does; that's no reason to complain.
+        if err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe) {
+            process::exit(0);
+        }
+
         eprintln!("Application error: {}", err);
         process::exit(-2);
     }
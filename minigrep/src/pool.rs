@@ -0,0 +1,103 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+
+/// The type of job that threads in the pool execute
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads that execute jobs from a shared channel
+///
+/// The same design as the thread pool of the `hello` web server, without its
+/// logging and statistics: output of the workers would get mixed into the matches.
+/// Dropping the pool waits for the queued jobs to finish.
+pub struct ThreadPool {
+    workers: Vec<thread::JoinHandle<()>>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+impl ThreadPool {
+    /// Create a pool of `size` worker threads
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero, or if the OS can't spawn a thread.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0, "Expected at least one thread in the pool.");
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = Arc::clone(&receiver);
+
+                thread::Builder::new()
+                    .name(format!("minigrep-worker-{id}"))
+                    .spawn(move || loop {
+                        // The lock is released before the job runs, so that other workers can take jobs meanwhile.
+                        let message = receiver.lock().expect("Expected to acquire the receiver's lock.").recv();
+
+                        match message {
+                            Ok(job) => job(),
+                            // The sender was dropped, so there is no more work.
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Expected the OS to spawn a worker thread.")
+            })
+            .collect();
+
+        ThreadPool { workers, sender: Some(sender) }
+    }
+
+    /// Queue `f` to be executed by the next free worker
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .as_ref().expect("Expected to extract sender from Some.")
+            .send(Box::new(f)).expect("Expected to send a job.");
+    }
+}
+
+impl Drop for ThreadPool {
+    /// Close the channel, and wait for the workers to finish what's queued
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            worker.join().expect("Expected to join a worker thread.");
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn executes_all_jobs_before_dropping() {
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let pool = ThreadPool::new(3);
+        for _ in 0..20 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        drop(pool);
+
+        assert_eq!(20, done.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected at least one thread in the pool.")]
+    fn zero_threads() {
+        ThreadPool::new(0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Write};
+
+use crate::color::Painted;
+use crate::context::Line;
+use crate::json::{JsonEvent, Submatch, Summary, Totals};
+use crate::{ColorChoice, Config, Searcher, Sink};
+
+
+/// Prints what a `Searcher` finds in the input named `name` the way grep does
+///
+/// `config` decides between lines, counts and file names, how lines look, and their colors.
+pub struct StandardPrinter<'a, W> {
+    out: W,
+    name: &'a str,
+    config: &'a Config,
+    color: bool,
+    with_filename: bool,
+    matched_lines: usize,
+}
+
+impl<'a, W: Write> StandardPrinter<'a, W> {
+    /// A printer that only colors with `--color=always`, and names the input with `-H`
+    pub fn new(out: W, name: &'a str, config: &'a Config) -> StandardPrinter<'a, W> {
+        let color = config.color == ColorChoice::Always;
+        let with_filename = config.with_filename.unwrap_or(false);
+
+        StandardPrinter { out, name, config, color, with_filename, matched_lines: 0 }
+    }
+
+    pub fn color(mut self, color: bool) -> StandardPrinter<'a, W> {
+        self.color = color;
+        self
+    }
+
+    pub fn with_filename(mut self, with_filename: bool) -> StandardPrinter<'a, W> {
+        self.with_filename = with_filename;
+        self
+    }
+
+    /// Whether only a count or the file name is printed, once the input is done
+    fn prints_lines(&self) -> bool {
+        !self.config.count && !self.config.files_with_matches
+    }
+
+    /// Print a line; `separator` is `:` for a selected line, and `-` for context
+    fn write_line(&mut self, searcher: &Searcher, line: &Line, separator: char) -> io::Result<()> {
+        let matcher = searcher.matcher();
+
+        if self.with_filename {
+            self.write_name(separator)?;
+        }
+        if self.config.line_number {
+            let theme = &self.config.theme;
+            write!(self.out, "{}{}", self.paint(&theme.line_number, line.number), self.paint(&theme.separator, separator))?;
+        }
+        // Inverted lines, and context lines, have no matches to highlight or replace.
+        let has_matches = !searcher.is_inverted() && separator == ':';
+        if let (Some(replacement), true) = (&self.config.replace, has_matches) {
+            writeln!(self.out, "{}", matcher.replace(line.text, replacement))
+        } else if self.color && has_matches {
+            writeln!(self.out, "{}", self.config.theme.highlight(line.text, &matcher.find_spans(line.text)))
+        } else {
+            writeln!(self.out, "{}", line.text)
+        }
+    }
+
+    /// Print the name of the input, followed by `separator`
+    ///
+    /// With `--null`, a NUL byte follows instead, which can't be part of a name.
+    fn write_name(&mut self, separator: char) -> io::Result<()> {
+        let theme = &self.config.theme;
+        let name = self.paint(&theme.path, self.name);
+
+        if self.config.null {
+            write!(self.out, "{}\0", name)
+        } else if separator == '\n' {
+            writeln!(self.out, "{}", name)
+        } else {
+            write!(self.out, "{}{}", name, self.paint(&theme.separator, separator))
+        }
+    }
+
+    /// `text` in `color`, if the output is colored
+    fn paint<'t, T>(&self, color: &'t str, text: T) -> Painted<'t, T> {
+        Painted(if self.color { color } else { "" }, text)
+    }
+}
+
+impl<W: Write> Sink for StandardPrinter<'_, W> {
+    type Error = io::Error;
+
+    fn matched(&mut self, searcher: &Searcher, line: &Line) -> io::Result<bool> {
+        self.matched_lines += 1;
+
+        if self.config.files_with_matches {
+            // One line is enough to know; the rest of the input doesn't matter.
+            return Ok(false);
+        }
+        if self.prints_lines() {
+            self.write_line(searcher, line, ':')?;
+        }
+        Ok(true)
+    }
+
+    fn context(&mut self, searcher: &Searcher, line: &Line) -> io::Result<bool> {
+        if self.prints_lines() {
+            self.write_line(searcher, line, '-')?;
+        }
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _searcher: &Searcher) -> io::Result<bool> {
+        if self.prints_lines() {
+            writeln!(self.out, "{}", self.paint(&self.config.theme.separator, "--"))?;
+        }
+        Ok(true)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher) -> io::Result<()> {
+        if self.config.files_with_matches {
+            if self.matched_lines > 0 {
+                self.write_name('\n')?;
+            }
+        } else if self.config.count {
+            if self.with_filename {
+                self.write_name(':')?;
+            }
+            writeln!(self.out, "{}", self.matched_lines)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints what a `Searcher` finds in the input named `name` as `--json` events
+///
+/// Once the input is done, its summary is added to `totals`.
+pub struct JsonPrinter<'a, W> {
+    out: W,
+    name: &'a str,
+    totals: &'a Totals,
+    summary: Summary,
+}
+
+impl<'a, W: Write> JsonPrinter<'a, W> {
+    pub fn new(out: W, name: &'a str, totals: &'a Totals) -> JsonPrinter<'a, W> {
+        JsonPrinter { out, name, totals, summary: Summary { files: 1, ..Summary::default() } }
+    }
+}
+
+impl<W: Write> Sink for JsonPrinter<'_, W> {
+    type Error = io::Error;
+
+    fn matched(&mut self, searcher: &Searcher, line: &Line) -> io::Result<bool> {
+        // Inverted lines have no matches.
+        let spans = if searcher.is_inverted() { Vec::new() } else { searcher.matcher().find_spans(line.text) };
+        self.summary.matched_lines += 1;
+        self.summary.matches += spans.len();
+
+        let submatches = spans.into_iter().map(|span| Submatch::new(line.text, span)).collect();
+        let event = JsonEvent::Match { path: self.name, line_number: line.number, offset: line.offset, line: line.text, submatches };
+        event.write(&mut self.out)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, _searcher: &Searcher, line: &Line) -> io::Result<bool> {
+        JsonEvent::Context { path: self.name, line_number: line.number, line: line.text }.write(&mut self.out)?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, _searcher: &Searcher) -> io::Result<()> {
+        if self.summary.matched_lines > 0 {
+            self.summary.files_with_matches = 1;
+        }
+        self.totals.add(&self.summary);
+        Ok(())
+    }
+}
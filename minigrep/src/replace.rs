@@ -0,0 +1,260 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
+
+use crate::decompress::Format;
+use crate::{Config, Matcher};
+
+
+/// How `rewrite_file()` treats a file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+    /// Rewrite binary files, too
+    pub text: bool,
+    /// Refuse to rewrite compressed files, as their decompressed lines were searched
+    pub decompress: bool,
+    /// Only report the changes
+    pub dry_run: bool,
+}
+
+impl Options {
+    /// The options that `config` asks for
+    pub fn new(config: &Config) -> Options {
+        Options { text: config.text, decompress: !config.no_decompress, dry_run: config.dry_run }
+    }
+}
+
+/// Replace the matches in the file at `path`, and return the number of lines that changed
+///
+/// The new contents are written to a temp file in the same directory, which is then
+/// renamed over the original, so that the file is never seen half written. Line endings
+/// are kept as they are. A file without changes is left alone. A symlink is followed,
+/// so that the file it points to is rewritten, and the link stays a link.
+///
+/// With `options.dry_run`, nothing is written. Either way, `on_change` is called with the
+/// line number, the old line and the new line of each changed line.
+///
+/// Binary files are skipped unless `options.text` is set. Files that aren't valid UTF-8 aren't
+/// rewritten, as that would mean replacing their invalid bytes; with `options.decompress`, neither
+/// are compressed files, whose decompressed lines were searched but can't be written back.
+pub fn rewrite_file(path: &Path, matcher: &Matcher, replacement: &str, options: Options, mut on_change: impl FnMut(usize, &str, &str)) -> io::Result<usize> {
+    // Renaming over a symlink would replace the link, and leave its target as it was.
+    let path = &fs::canonicalize(path)?;
+    let mut reader = BufReader::new(File::open(path)?);
+    if options.decompress && Format::detect(reader.fill_buf()?).is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the file is compressed; not rewriting it"));
+    }
+    if !options.text && reader.fill_buf()?.contains(&0) {
+        return Ok(0);
+    }
+
+    let mut temp = if options.dry_run { None } else { Some(TempFile::create(path)?) };
+    let mut line = Vec::new();
+    let mut line_number = 0;
+    let mut changed = 0;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        let (content, ending) = split_line_ending(&line);
+        let content = str::from_utf8(content).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {line_number} isn't valid UTF-8; not rewriting the file"))
+        })?;
+
+        let new = matcher.replace(content, replacement);
+        if new != content {
+            changed += 1;
+            on_change(line_number, content, &new);
+        }
+
+        if let Some(temp) = &mut temp {
+            temp.writer.write_all(new.as_bytes())?;
+            temp.writer.write_all(ending)?;
+        }
+    }
+
+    if let Some(temp) = temp {
+        if changed > 0 {
+            temp.replace(path)?;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Split `line` into its content and its line ending, `\n`, `\r\n` or nothing
+fn split_line_ending(line: &[u8]) -> (&[u8], &[u8]) {
+    let len = if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    };
+
+    line.split_at(line.len() - len)
+}
+
+/// A temp file next to the file that it will replace, with the same permissions, and on Unix,
+/// the same owner and group, as far as the user may give them away
+///
+/// It's deleted on drop, unless it has replaced the file.
+struct TempFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl TempFile {
+    fn create(original: &Path) -> io::Result<TempFile> {
+        let name = original.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+        let path = original.with_file_name(format!(".{}.minigrep-{}.tmp", name.to_string_lossy(), process::id()));
+
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        let metadata = fs::metadata(original)?;
+        file.set_permissions(metadata.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+
+            // Only root can give a file away; anyone can keep the group of a file they own
+            // if they're in it. Whatever is refused stays the user's own, as with any new file.
+            if fchown(&file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+                let _ = fchown(&file, None, Some(metadata.gid()));
+            }
+        }
+
+        Ok(TempFile { path, writer: BufWriter::new(file) })
+    }
+
+    /// Move the temp file over `original`, once all of it is on disk
+    fn replace(mut self, original: &Path) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        fs::rename(&self.path, original)?;
+
+        // It's gone now; nothing to clean up on drop.
+        self.path = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("minigrep-replace-{name}-{}.txt", process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const DECOMPRESS: Options = Options { text: false, decompress: true, dry_run: false };
+
+    fn matcher(query: &str, regex: bool) -> Matcher {
+        Matcher::build(&Config { patterns: vec![query.to_string()], regex, ..Config::default() }).unwrap()
+    }
+
+    #[test]
+    fn rewrites_matching_lines_keeping_line_endings() {
+        let path = file("rewrite", b"a bird\r\nno match\nbird, bird");
+        let mut changes = Vec::new();
+
+        let changed = rewrite_file(&path, &matcher(r"b(i)rd", true), "sw${1}ft", DECOMPRESS, |number, old, new| {
+            changes.push((number, old.to_string(), new.to_string()));
+        })
+        .unwrap();
+
+        assert_eq!(2, changed);
+        assert_eq!(vec![(1, "a bird".to_string(), "a swift".to_string()), (3, "bird, bird".to_string(), "swift, swift".to_string())], changes);
+        assert_eq!(b"a swift\r\nno match\nswift, swift".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() {
+        let path = file("dry-run", b"a bird\n");
+
+        let changed = rewrite_file(&path, &matcher("bird", false), "swan", Options { dry_run: true, ..DECOMPRESS }, |_, _, _| {}).unwrap();
+
+        assert_eq!(1, changed);
+        assert_eq!(b"a bird\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_utf8_is_not_rewritten() {
+        let path = file("invalid", b"a bird\ncaf\xe9\n");
+
+        let err = rewrite_file(&path, &matcher("bird", false), "swan", DECOMPRESS, |_, _, _| {}).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(b"a bird\ncaf\xe9\n".to_vec(), fs::read(&path).unwrap());
+        let temp_files = fs::read_dir(env::temp_dir()).unwrap().filter(|entry| {
+            entry.as_ref().unwrap().file_name().to_string_lossy().contains(&format!("minigrep-replace-invalid-{}.txt.minigrep", process::id()))
+        });
+        assert_eq!(0, temp_files.count(), "Expected the temp file to be deleted.");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compressed_files_are_not_rewritten() {
+        let path = file("compressed", b"\x1f\x8b\x08\x00 a bird\n");
+
+        let err = rewrite_file(&path, &matcher("bird", false), "swan", Options { text: true, ..DECOMPRESS }, |_, _, _| {}).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(b"\x1f\x8b\x08\x00 a bird\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compressed_looking_files_are_rewritten_without_decompression() {
+        let path = file("bzh", b"BZh9 a bird\n");
+
+        let changed = rewrite_file(&path, &matcher("bird", false), "swan", Options { decompress: false, ..DECOMPRESS }, |_, _, _| {}).unwrap();
+
+        assert_eq!(1, changed);
+        assert_eq!(b"BZh9 a swan\n".to_vec(), fs::read(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_followed() {
+        let target = file("target", b"a bird\n");
+        let link = env::temp_dir().join(format!("minigrep-replace-link-{}.txt", process::id()));
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let changed = rewrite_file(&link, &matcher("bird", false), "swan", DECOMPRESS, |_, _, _| {}).unwrap();
+
+        assert_eq!(1, changed);
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink(), "Expected the link to stay a link.");
+        assert_eq!(b"a swan\n".to_vec(), fs::read(&target).unwrap());
+
+        fs::remove_file(&link).unwrap();
+        fs::remove_file(&target).unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use crate::context::{Context, Event, Line};
+use crate::{Config, Input, Matcher, PatternError};
+
+
+/// A match that a `Searcher` found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Counting from 1
+    pub line_number: usize,
+    /// Where the match is in `line`
+    pub byte_range: Range<usize>,
+    /// The line of the match, without its line ending
+    pub line: String,
+}
+
+/// Searches input line by line for the lines that a `Matcher` selects
+///
+/// `matches()` yields the matches one by one. `search()` hands the selected lines, and the
+/// context around them, to a `Sink`, which decides what to make of them; that's how the
+/// binary prints its output.
+pub struct Searcher {
+    matcher: Matcher,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    text: bool,
+    rank_by_distance: bool,
+}
+
+impl Searcher {
+    /// A searcher for the lines that `matcher` matches, without context, that skips binary input
+    pub fn new(matcher: Matcher) -> Searcher {
+        Searcher { matcher, invert_match: false, before_context: 0, after_context: 0, text: false, rank_by_distance: false }
+    }
+
+    /// Build the searcher that `config` asks for
+    pub fn build(config: &Config) -> Result<Searcher, PatternError> {
+        let searcher = Searcher::new(Matcher::build(config)?)
+            .invert_match(config.invert_match)
+            .context(config.before_context, config.after_context)
+            .text(config.text)
+            .rank_by_distance(config.fuzzy.is_some());
+
+        Ok(searcher)
+    }
+
+    /// Select the lines that don't match instead
+    pub fn invert_match(mut self, invert_match: bool) -> Searcher {
+        self.invert_match = invert_match;
+        self
+    }
+
+    /// Hand up to `before` lines before each selected line, and `after` lines after it, to the sink
+    pub fn context(mut self, before: usize, after: usize) -> Searcher {
+        self.before_context = before;
+        self.after_context = after;
+        self
+    }
+
+    /// Search binary input as text, instead of skipping it
+    pub fn text(mut self, text: bool) -> Searcher {
+        self.text = text;
+        self
+    }
+
+    /// Hand the selected lines to the sink closest first, for fuzzy matching
+    ///
+    /// The lines are collected until the end of the input, and handed on without context.
+    /// The lines of an inverted search have no distance; they stay in order.
+    pub fn rank_by_distance(mut self, rank_by_distance: bool) -> Searcher {
+        self.rank_by_distance = rank_by_distance;
+        self
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    pub fn is_inverted(&self) -> bool {
+        self.invert_match
+    }
+
+    /// The matches in `reader`, in order
+    ///
+    /// A line with several matches yields a `Match` for each. The lines of an inverted search
+    /// have no matches; each yields one whose range covers the whole line, and so does a line
+    /// that only matches emptily, like with `x*`. Binary input yields nothing, unless `text` is set.
+    pub fn matches<'a>(&'a self, reader: impl BufRead + 'a) -> Matches<'a> {
+        Matches { searcher: self, input: Input::new(Box::new(reader)), pending: VecDeque::new(), started: false, done: false }
+    }
+
+    /// Search `input`, and hand the selected lines, and the context around them, to `sink`
+    ///
+    /// Binary input is skipped without a word to the sink, unless `text` is set. Otherwise,
+    /// `sink.finish()` is called once the input ends, the sink asks to stop, or reading fails.
+    pub fn search<S: Sink>(&self, mut input: Input, sink: &mut S) -> Result<(), SearchError<S::Error>> {
+        match input.is_binary() {
+            Ok(true) if !self.text => return Ok(()),
+            Ok(_) => {}
+            Err(err) => return Err(SearchError::Read(err)),
+        }
+
+        let searched = self.search_lines(&mut input, sink);
+        sink.finish(self).map_err(SearchError::Sink)?;
+        searched
+    }
+
+    fn search_lines<S: Sink>(&self, input: &mut Input, sink: &mut S) -> Result<(), SearchError<S::Error>> {
+        let mut context = Context::new(self.before_context, self.after_context);
+        let mut go_on = true;
+        let mut read_error = None;
+
+        // Ranked lines are handed on best first, so they have to be collected before any is.
+        let ranked = self.rank_by_distance && !self.invert_match;
+        let mut ranking = Vec::new();
+
+        while go_on {
+            let offset = input.offset();
+            let (number, text) = match input.next_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(err) => {
+                    read_error = Some(err);
+                    break;
+                }
+            };
+
+            let selected = self.matcher.is_match(&text) != self.invert_match;
+
+            if ranked {
+                if selected {
+                    let distance = self.matcher.distance(&text).unwrap_or(0);
+                    ranking.push((distance, number, offset, text.into_owned()));
+                }
+                continue;
+            }
+
+            let line = Line { number, offset, text: &text };
+            context
+                .push(line, selected, |event| {
+                    if go_on {
+                        go_on = self.hand_on(sink, event)?;
+                    }
+                    Ok(())
+                })
+                .map_err(SearchError::Sink)?;
+        }
+
+        // Sorting is stable, so lines at the same distance stay in order.
+        ranking.sort_by_key(|&(distance, ..)| distance);
+        for (_, number, offset, text) in ranking {
+            if !sink.matched(self, &Line { number, offset, text: &text }).map_err(SearchError::Sink)? {
+                break;
+            }
+        }
+
+        read_error.map_or(Ok(()), |err| Err(SearchError::Read(err)))
+    }
+
+    /// Hand `event` to the matching method of `sink`, and return whether to go on
+    fn hand_on<S: Sink>(&self, sink: &mut S, event: Event) -> Result<bool, S::Error> {
+        match event {
+            Event::Separator => sink.context_break(self),
+            Event::Match(line) => sink.matched(self, &line),
+            Event::Context(line) => sink.context(self, &line),
+        }
+    }
+}
+
+/// Receives what a `Searcher` finds in an input
+///
+/// All methods but `finish()` return whether to go on searching the input.
+pub trait Sink {
+    type Error;
+
+    /// A selected line
+    fn matched(&mut self, searcher: &Searcher, line: &Line) -> Result<bool, Self::Error>;
+
+    /// A line around a selected line, when the searcher has context
+    fn context(&mut self, _searcher: &Searcher, _line: &Line) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
+    /// Between groups of lines that aren't adjacent, when the searcher has context
+    fn context_break(&mut self, _searcher: &Searcher) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+
+    /// The search of the input is over
+    fn finish(&mut self, _searcher: &Searcher) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Why `Searcher::search()` failed
+#[derive(Debug)]
+pub enum SearchError<E> {
+    /// The input couldn't be read
+    Read(io::Error),
+    /// The sink failed
+    Sink(E),
+}
+
+impl<E: Display> Display for SearchError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::Read(err) => write!(f, "{}", err),
+            SearchError::Sink(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<E: Error> Error for SearchError<E> {}
+
+/// The matches of a `Searcher` in an input; see `Searcher::matches()`
+///
+/// An error while reading is yielded once, and ends the matches.
+pub struct Matches<'a> {
+    searcher: &'a Searcher,
+    input: Input<'a>,
+    /// The matches of the last line read that haven't been yielded yet
+    pending: VecDeque<Match>,
+    /// Whether the input was checked for binary content
+    started: bool,
+    done: bool,
+}
+
+impl Matches<'_> {
+    /// Read the next line, and queue its matches
+    fn read_line(&mut self) -> io::Result<()> {
+        let &Searcher { ref matcher, invert_match, text, .. } = self.searcher;
+
+        if !self.started {
+            self.started = true;
+            if !text && self.input.is_binary()? {
+                self.done = true;
+                return Ok(());
+            }
+        }
+
+        let Some((line_number, line)) = self.input.next_line()? else {
+            self.done = true;
+            return Ok(());
+        };
+
+        let spans = if invert_match { Vec::new() } else { matcher.find_spans(&line) };
+        if spans.is_empty() {
+            if matcher.is_match(&line) != invert_match {
+                self.pending.push_back(Match { line_number, byte_range: 0..line.len(), line: line.into_owned() });
+            }
+            return Ok(());
+        }
+
+        for byte_range in spans {
+            self.pending.push_back(Match { line_number, byte_range, line: line.to_string() });
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = io::Result<Match>;
+
+    fn next(&mut self) -> Option<io::Result<Match>> {
+        loop {
+            if let Some(found) = self.pending.pop_front() {
+                return Some(Ok(found));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.read_line() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn searcher(query: &str) -> Searcher {
+        Searcher::new(Matcher::build(&Config { patterns: vec![query.to_string()], ..Config::default() }).unwrap())
+    }
+
+    const POEM: &[u8] = b"Is there a bird, a bird?\nPerhaps a flamingo?\nOr a swan?\n";
+
+    #[test]
+    fn matches_with_ranges() {
+        let matches: Vec<Match> = searcher("bird").matches(POEM).map(Result::unwrap).collect();
+
+        assert_eq!(2, matches.len());
+        assert_eq!(Match { line_number: 1, byte_range: 11..15, line: "Is there a bird, a bird?".to_string() }, matches[0]);
+        assert_eq!(19..23, matches[1].byte_range);
+
+        let inverted: Vec<(usize, Range<usize>)> =
+            searcher("bird").invert_match(true).matches(POEM).map(|found| found.map(|found| (found.line_number, found.byte_range)).unwrap()).collect();
+        assert_eq!(vec![(2, 0..19), (3, 0..10)], inverted);
+
+        assert_eq!(0, searcher("bird").matches(&b"a bird\0"[..]).count());
+        assert_eq!(1, searcher("bird").text(true).matches(&b"a bird\0"[..]).count());
+    }
+
+    /// Records what it's handed, and stops after `limit` selected lines
+    struct Recorder {
+        events: Vec<String>,
+        limit: usize,
+    }
+
+    impl Sink for Recorder {
+        type Error = io::Error;
+
+        fn matched(&mut self, _searcher: &Searcher, line: &Line) -> io::Result<bool> {
+            self.events.push(format!("{}@{}:{}", line.number, line.offset, line.text));
+            self.limit -= 1;
+            Ok(self.limit > 0)
+        }
+
+        fn context(&mut self, _searcher: &Searcher, line: &Line) -> io::Result<bool> {
+            self.events.push(format!("{}@{}-{}", line.number, line.offset, line.text));
+            Ok(true)
+        }
+
+        fn context_break(&mut self, _searcher: &Searcher) -> io::Result<bool> {
+            self.events.push("--".to_string());
+            Ok(true)
+        }
+
+        fn finish(&mut self, _searcher: &Searcher) -> io::Result<()> {
+            self.events.push("done".to_string());
+            Ok(())
+        }
+    }
+
+    fn record(searcher: &Searcher, bytes: &'static [u8], limit: usize) -> Vec<String> {
+        let mut recorder = Recorder { events: Vec::new(), limit };
+        searcher.search(Input::new(Box::new(bytes)), &mut recorder).unwrap();
+        recorder.events
+    }
+
+    #[test]
+    fn sinks_get_lines_with_context() {
+        let contents = b"a bird\none\ntwo\nthree\nanother bird\nfour\n";
+
+        assert_eq!(vec!["1@0:a bird", "5@21:another bird", "done"], record(&searcher("bird"), contents, 10));
+        assert_eq!(
+            vec!["1@0:a bird", "2@7-one", "--", "4@15-three", "5@21:another bird", "6@34-four", "done"],
+            record(&searcher("bird").context(1, 1), contents, 10),
+        );
+        assert_eq!(vec!["1@0:a bird", "done"], record(&searcher("bird"), contents, 1), "Expected the sink to stop the search.");
+        assert!(record(&searcher("bird"), b"a bird\0", 10).is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use std::iter;
+use std::path::PathBuf;
+
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+
+use crate::config::Config;
+use crate::index::INDEX_FILE;
+use crate::input::STDIN_PATH;
+
+
+/// The files to search, found by walking the paths of `config`
+///
+/// Directories are walked recursively. Unless `config` says otherwise, hidden
+/// files and files ignored by `.gitignore` or `.ignore` files are skipped,
+/// also outside of git repositories. The include and exclude globs of
+/// `config`, and the skipping of index files, only apply to what is found
+/// in directories.
+///
+/// `-` is passed through as is, for standard input. Files come in the order
+/// of their paths; with `config.sort`, the files in directories are sorted
+/// by name, too.
+pub fn files(config: &Config) -> Result<impl Iterator<Item = Result<PathBuf, ignore::Error>> + '_, ignore::Error> {
+    let overrides = globs(config)?;
+
+    let files = config.paths.iter().flat_map(move |path| -> Box<dyn Iterator<Item = _>> {
+        if path == STDIN_PATH {
+            return Box::new(iter::once(Ok(PathBuf::from(path))));
+        }
+
+        let mut builder = WalkBuilder::new(path);
+        if config.sort {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+
+        let walk = builder
+            .hidden(!config.hidden)
+            .ignore(!config.no_ignore)
+            .git_ignore(!config.no_ignore)
+            .git_global(!config.no_ignore)
+            .git_exclude(!config.no_ignore)
+            .parents(!config.no_ignore)
+            .require_git(false)
+            .overrides(overrides.clone())
+            .build();
+
+        Box::new(walk.filter_map(|entry| match entry {
+            // The index, and the temp file it's written to while it's updated, are only searched when asked for.
+            Ok(entry) if entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with(INDEX_FILE) => None,
+            Ok(entry) if entry.file_type().is_some_and(|file_type| file_type.is_file()) => Some(Ok(entry.into_path())),
+            Ok(_) => None,
+            Err(error) => Some(Err(error)),
+        }))
+    });
+
+    Ok(files)
+}
+
+/// Turn the include and exclude globs into overrides
+///
+/// Excludes are added last, so that they take precedence.
+fn globs(config: &Config) -> Result<Override, ignore::Error> {
+    let mut builder = OverrideBuilder::new(".");
+
+    for glob in &config.include {
+        builder.add(glob)?;
+    }
+    for glob in &config.exclude {
+        builder.add(&format!("!{glob}"))?;
+    }
+
+    builder.build()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    use super::*;
+
+    /// A directory tree with a `.gitignore`, hidden files and nested directories
+    fn tree(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("minigrep-walk-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for dir in ["src/nested", "target", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["src/lib.rs", "src/nested/mod.rs", "src/notes.txt", "target/out.rs", ".hidden/secret.rs", ".env", "README.md"] {
+            fs::write(root.join(file), "bird").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+
+        root
+    }
+
+    fn walk(root: &Path, configure: impl FnOnce(&mut Config)) -> Vec<String> {
+        let mut config = Config { paths: vec![root.display().to_string()], ..Config::default() };
+        configure(&mut config);
+
+        let mut found: Vec<String> = files(&config)
+            .unwrap()
+            .map(|path| path.unwrap().strip_prefix(root).unwrap().display().to_string())
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn respects_gitignore_and_hidden_files() {
+        let root = tree("default");
+
+        assert_eq!(vec!["README.md", "src/lib.rs", "src/nested/mod.rs", "src/notes.txt"], walk(&root, |_| {}));
+
+        let all = walk(&root, |config| {
+            config.hidden = true;
+            config.no_ignore = true;
+        });
+        assert!(all.contains(&".env".to_string()));
+        assert!(all.contains(&".gitignore".to_string()));
+        assert!(all.contains(&".hidden/secret.rs".to_string()));
+        assert!(all.contains(&"target/out.rs".to_string()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn include_and_exclude_globs() {
+        let root = tree("globs");
+
+        assert_eq!(vec!["src/lib.rs", "src/nested/mod.rs"], walk(&root, |config| config.include.push("*.rs".to_string())));
+
+        let found = walk(&root, |config| {
+            config.include.push("*.rs".to_string());
+            config.include.push("*.md".to_string());
+            config.exclude.push("nested".to_string());
+        });
+        assert_eq!(vec!["README.md", "src/lib.rs"], found);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn explicit_files_are_always_searched() {
+        let root = tree("explicit");
+
+        let mut config = Config { paths: vec![root.join(".env").display().to_string()], ..Config::default() };
+        config.include.push("*.rs".to_string());
+        let found: Vec<PathBuf> = files(&config).unwrap().map(Result::unwrap).collect();
+        assert_eq!(vec![root.join(".env")], found);
+
+        fs::write(root.join(INDEX_FILE), "bird").unwrap();
+        assert!(!walk(&root, |config| config.hidden = true).contains(&INDEX_FILE.to_string()));
+        let config = Config { paths: vec![root.join(INDEX_FILE).display().to_string()], ..Config::default() };
+        assert_eq!(1, files(&config).unwrap().count(), "Expected an index file to be searched when it's asked for.");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sorted() {
+        let root = tree("sorted");
+
+        let config = Config { paths: vec![root.display().to_string()], sort: true, ..Config::default() };
+        let found: Vec<PathBuf> = files(&config).unwrap().map(Result::unwrap).collect();
+        let expected: Vec<PathBuf> = ["README.md", "src/lib.rs", "src/nested/mod.rs", "src/notes.txt"].iter().map(|path| root.join(path)).collect();
+        assert_eq!(expected, found);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn stdin_is_passed_through_in_order() {
+        let root = tree("stdin");
+
+        let config = Config { paths: vec!["-".to_string(), root.join("README.md").display().to_string(), "-".to_string()], ..Config::default() };
+        let found: Vec<PathBuf> = files(&config).unwrap().map(Result::unwrap).collect();
+        assert_eq!(vec![PathBuf::from("-"), root.join("README.md"), PathBuf::from("-")], found);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}